@@ -1,15 +1,66 @@
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use encase::ShaderType;
 use glam::Vec2Swizzles;
 use rug::ops::CompleteRound;
 use wgpu::util::DeviceExt;
 use winit::{event::WindowEvent, window::Window};
 
-const CENTER: &'static str = "(-0.599937010146780929103754562, -0.4293244312274789964509138456)";
+pub const CENTER: &str = "(-0.599937010146780929103754562, -0.4293244312274789964509138456)";
+pub const ZOOM: f64 = 2.5;
 const ITERATIONS: usize = 600;
 
+// Range of the iteration limit, and how the automatic mode moves it. The limit is raised once
+// more than this fraction of samples reaches it without settling into a cycle, and lowered once
+// the last escape is far enough below it.
+pub const MIN_ITERATIONS: usize = 100;
+pub const MAX_ITERATIONS: usize = 100_000;
+const RAISE_FRACTION: f32 = 0.01;
+const RAISE_FACTOR: usize = 2;
+const LOWER_FACTOR: usize = 4;
+// Multiple of the last escape a lowered limit leaves room for, so nearby views don't immediately
+// raise it again. Must stay below `LOWER_FACTOR`, or the next frame lowers it once more.
+const HEADROOM: usize = 2;
+
+// Must be kept in sync with `MAX_FOOTPRINT` in render.wgsl
+pub const MIN_RENDER_SCALE: f32 = 0.25;
+pub const MAX_RENDER_SCALE: f32 = 4.0;
+
+// Upper bound on the workgroups of the histogram pass, each of which merges its counts globally
+const HISTOGRAM_WORKGROUPS: u32 = 256;
+
+// Resolution multiplier applied while dragging or zooming, and how long to wait before restoring
+const NAVIGATION_SCALE: f32 = 0.5;
+const NAVIGATION_SETTLE_TIME: f32 = 0.25;
+
+// Distance from the center, relative to the height of the view, beyond which the reference orbit
+// is moved back to the center
+const REFERENCE_DRIFT: f32 = 0.5;
+
+// How close a pan has to come to a whole number of pixels for the engine to reuse the image
+const SHIFT_TOLERANCE: f64 = 1e-3;
+
 use crate::{
-    gui, mandelbrot, pipeline,
-    precision::PRECISION,
+    colouring::Colouring,
+    df64::Df64,
+    engine::{self, cpu::CpuEngine, gpu::GpuEngine, Engine, EngineKind},
+    exponential::{self, Projection},
+    gui, histogram, mandelbrot,
+    palette::{self, Palette},
+    pipeline,
+    precision::{Precision, PRECISION},
+    profiler::{self, Profiler, Stage},
+    reference, refinement,
+    sampling::{self, Reconstruction},
+    statistics::{self, Statistics},
     storage::{self, Storable},
+    trap::{self, Trap},
 };
 
 const QUAD_VERTICIES: &[storage::Vertex] = &[
@@ -36,14 +87,152 @@ const QUAD_INDICES: &[u32] = &[0, 1, 2, 2, 3, 0];
 pub struct State {
     globals: Globals,
     surface: wgpu::Surface,
-    device: wgpu::Device,
-    queue: wgpu::Queue,
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
     config: wgpu::SurfaceConfiguration,
     size: winit::dpi::PhysicalSize<u32>,
     pipelines: Pipelines,
-    compute_data: ComputeData,
     render_data: RenderData,
     gui_layer: GuiLayer,
+    engine: Box<dyn Engine>,
+    last_navigation: Option<std::time::Instant>,
+    rendered: Rendered,
+    // When the GUI asked to be drawn again
+    repaint_at: Option<std::time::Instant>,
+    // Part of the dragged distance, in pixels, not yet applied to the center
+    pan_remainder: glam::f32::Vec2,
+}
+
+// The parameters and view of the last frame handed to the engine. Needs no device, so it can be
+// driven with any engine and target.
+#[derive(Default)]
+struct Rendered {
+    last: Option<(ViewParameters, engine::View)>,
+}
+
+impl Rendered {
+    fn view(&self) -> Option<&engine::View> {
+        self.last.as_ref().map(|(_, view)| view)
+    }
+
+    fn dirty(&self, parameters: &ViewParameters) -> bool {
+        self.last.as_ref().map(|(p, _)| p) != Some(parameters)
+    }
+
+    // Builds a new view when its parameters changed, returns whether they did
+    fn update(&mut self, globals: &Globals, width: u32, height: u32) -> bool {
+        let parameters = globals.view_parameters(width, height);
+        let dirty = self.dirty(&parameters);
+        if dirty {
+            let mut view = globals.view(width, height);
+            view.shift = self
+                .last
+                .as_ref()
+                .and_then(|(previous, _)| parameters.shift_from(previous));
+
+            self.last = Some((parameters, view));
+        }
+        dirty
+    }
+
+    // Hands the view to the engine when it is new or still being worked on, returns whether it did
+    fn render(&self, engine: &mut dyn Engine, dirty: bool, target: engine::Target) -> bool {
+        let rendering = dirty || engine.progress() < 1.0;
+        if let Some((_, view)) = self.last.as_ref().filter(|_| rendering) {
+            engine.render(view, target);
+        }
+        rendering
+    }
+}
+
+// Everything that affects the rendered image, compared between frames to detect changes
+#[derive(Clone, PartialEq)]
+struct ViewParameters {
+    width: u32,
+    height: u32,
+    zoom: rug::Float,
+    radius: rug::Float,
+    center: rug::Complex,
+    reference: rug::Complex,
+    z0: rug::Complex,
+    stripe_density: f32,
+    traps: Vec<Trap>,
+    subdivision: bool,
+    supersampling: u32,
+    refine: bool,
+    refine_threshold: f32,
+    progressive: bool,
+    projection: Projection,
+    precision: Precision,
+    reference_grid: u32,
+    iterations: usize,
+}
+
+impl ViewParameters {
+    // Whole pixels the image moved by since the previous parameters, when nothing but the center
+    // changed and it moved by a whole number of pixels. Exponential maps never shift.
+    fn shift_from(&self, previous: &ViewParameters) -> Option<glam::IVec2> {
+        let moved = ViewParameters {
+            center: previous.center.clone(),
+            ..self.clone()
+        };
+
+        if moved != *previous || self.projection == Projection::Exponential {
+            return None;
+        }
+
+        let pixel_size = self.zoom.clone().exp().recip() / self.height;
+        let delta = (self.center.clone() - &previous.center) / pixel_size;
+        let pixels = glam::dvec2(delta.real().to_f64(), delta.imag().to_f64());
+        let whole = pixels.round();
+        if (pixels - whole).abs().max_element() > SHIFT_TOLERANCE {
+            return None;
+        }
+
+        // The image moves the opposite way of the center
+        Some(-whole.as_ivec2())
+    }
+
+    // Maps positions on a view with these parameters onto the image of another one. Exponential
+    // maps only follow zooming, which moves them vertically, and show nothing of the other image
+    // otherwise.
+    fn reprojection(&self, other: &ViewParameters) -> storage::Reprojection {
+        if self.projection != other.projection || self.projection == Projection::Exponential {
+            let aligned = self.projection == other.projection
+                && self.center == other.center
+                && self.width == other.width
+                && self.height == other.height;
+
+            if !aligned {
+                return storage::Reprojection {
+                    scale: glam::f32::Vec2::ZERO,
+                    offset: glam::f32::Vec2::NEG_ONE,
+                };
+            }
+
+            let zoomed = (self.zoom.clone() - &other.zoom).to_f32();
+            let rows = zoomed * self.width as f32 / std::f32::consts::TAU;
+            return storage::Reprojection {
+                scale: glam::f32::Vec2::ONE,
+                offset: glam::f32::vec2(0.0, -rows / self.height as f32),
+            };
+        }
+
+        let aspect_ratio =
+            |p: &ViewParameters| glam::f32::vec2(p.width as f32 / p.height as f32, 1.0);
+
+        // Both the ratio and the offset stay small while zooming, so precision is only lost after
+        // the division by the scale of the other view
+        let ratio = (other.zoom.clone() - &self.zoom).exp().to_f32();
+        let scale = ratio * aspect_ratio(self) / aspect_ratio(other);
+        let offset = (self.center.clone() - &other.center) * other.zoom.clone().exp();
+        let offset = glam::f32::vec2(offset.real().to_f32(), offset.imag().to_f32());
+
+        storage::Reprojection {
+            scale,
+            offset: 0.5 - 0.5 * scale + offset / aspect_ratio(other),
+        }
+    }
 }
 
 pub struct Timing {
@@ -52,6 +241,8 @@ pub struct Timing {
     pub reference_time: std::time::Instant,
     pub last_checkpoint: std::time::Instant,
     pub frames_since_last_checkpoint: usize,
+    // Times of the last measured frames, oldest first, see profiler.rs
+    pub frames: VecDeque<profiler::FrameTimes>,
 }
 
 pub struct Globals {
@@ -61,22 +252,89 @@ pub struct Globals {
     pub center: rug::Complex,
     pub reference: rug::Complex,
     pub z0: rug::Complex,
+    pub colouring: Colouring,
+    pub equalize: bool,
+    pub palette: Palette,
+    // Bin counts and statistics of the last rendered frame, read back from the GPU for display
+    pub histogram: Vec<u32>,
+    pub statistics: Statistics,
+    pub stripe_density: f32,
+    pub traps: Vec<Trap>,
+    pub subdivision: bool,
+    pub show_subdivision: bool,
+    pub progressive: bool,
+    // Samples per pixel along each axis, and how they are combined
+    pub supersampling: u32,
+    pub reconstruction: Reconstruction,
+    // Adaptive refinement of high variance pixels, and whether to highlight them
+    pub refine: bool,
+    pub refine_threshold: f32,
+    pub show_refinement: bool,
+    pub render_scale: f32,
+    pub dynamic_resolution: bool,
+    pub engine: EngineKind,
+    pub projection: Projection,
+    pub precision: Precision,
+    // References along each axis of the view, see reference.rs
+    pub reference_grid: u32,
+    // Length of the reference orbits, and whether it follows the statistics of rendered frames
+    pub iterations: usize,
+    pub auto_iterations: bool,
+    // Whether the device supports f64 in shaders, negotiated at startup
+    pub shader_f64: bool,
+    // Whether passes are timed on the GPU, negotiated at startup
+    pub timestamps: bool,
 }
 
 pub struct Pipelines {
-    compute: pipeline::compute::ComputePipeline,
+    histogram: pipeline::histogram::HistogramPipeline,
+    colouring: pipeline::colouring::ColouringPipeline,
     render: pipeline::render::RenderPipeline,
 }
 
 pub struct RenderData {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
+    colouring_buffer: wgpu::Buffer,
+    // Written by the engine and turned into colours by the colouring pass, with a plane of
+    // channels for every sample and the samples of refined pixels
+    buffers: engine::Buffers,
+    sample_buffer: wgpu::Buffer,
+    samples: Vec<glam::f32::Vec2>,
+    // Written by the colouring pass and drawn onto the surface
+    output_texture: wgpu::Texture,
+    output_view: wgpu::TextureView,
+    // Lookup table of the palette it was last uploaded from
+    palette_texture: wgpu::Texture,
+    palette_view: wgpu::TextureView,
+    palette: Palette,
+    histogram: HistogramData,
+    placeholder: Placeholder,
+    profiler: Profiler,
 }
 
-pub struct ComputeData {
-    globals_buffer: wgpu::Buffer,
-    orbit_buffer: wgpu::Buffer,
-    render_texture: wgpu::TextureView,
+// Copy of the last complete image, drawn where a new view has pixels left to render
+pub struct Placeholder {
+    texture: wgpu::Texture,
+    view: wgpu::TextureView,
+    reprojection_buffer: wgpu::Buffer,
+    // Parameters of the image in the texture, if there is one yet
+    parameters: Option<ViewParameters>,
+}
+
+// Bin counts and cumulative distribution of the iteration counts in the channel buffer, and the
+// statistics reduced alongside them
+pub struct HistogramData {
+    counts_buffer: wgpu::Buffer,
+    cdf_buffer: wgpu::Buffer,
+    statistics_buffer: wgpu::Buffer,
+    // Bin counts followed by the words of the statistics
+    readback_buffer: wgpu::Buffer,
+    // Set by the map callback while a readback is in flight
+    readback: Option<Arc<AtomicBool>>,
+    // Whether the channels changed since the last readback was requested, which has to wait until
+    // the one in flight finishes, possibly after the engine is done
+    stale: bool,
 }
 
 pub struct GuiLayer {
@@ -88,31 +346,15 @@ pub struct GuiLayer {
 }
 
 impl State {
-    pub async fn new(window: &Window) -> Self {
+    pub async fn new(window: &Window) -> Result<Self, String> {
         let size = window.inner_size();
 
-        let globals = {
+        let mut globals = {
             let center = rug::Complex::parse(CENTER)
                 .expect("Unable to parse complex number")
                 .complete((PRECISION, PRECISION));
 
-            Globals {
-                timing: {
-                    let now = std::time::Instant::now();
-                    Timing {
-                        time: 0.0,
-                        avs_fps: 0.0,
-                        reference_time: now,
-                        last_checkpoint: now,
-                        frames_since_last_checkpoint: 0,
-                    }
-                },
-                z0: rug::Complex::with_val(PRECISION, (0.0, 0.0)),
-                zoom: rug::Float::with_val(PRECISION, 2.5),
-                radius: rug::Float::with_val(PRECISION, 2.0),
-                reference: center.clone(),
-                center,
-            }
+            Globals::new(center, rug::Float::with_val(PRECISION, ZOOM))
         };
 
         // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
@@ -123,28 +365,52 @@ impl State {
 
         // The surface needs to live as long as the window that created it.
         // State owns the window so this should be safe.
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
+        let surface = unsafe { instance.create_surface(&window) }.map_err(|e| e.to_string())?;
+
+        // Fall back to a software adapter when no hardware adapter is available
+        let mut adapter = None;
+        for force_fallback_adapter in [false, true] {
+            adapter = instance
+                .request_adapter(&wgpu::RequestAdapterOptions {
+                    power_preference: wgpu::PowerPreference::HighPerformance,
+                    compatible_surface: Some(&surface),
+                    force_fallback_adapter,
+                })
+                .await;
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
+            if adapter.is_some() {
+                break;
+            }
+        }
+
+        let adapter = adapter.ok_or("No suitable graphics adapter found")?;
+
+        // A software adapter is only used for presenting, rendering happens on the CPU engine
+        if adapter.get_info().device_type == wgpu::DeviceType::Cpu {
+            eprintln!("No hardware adapter found, falling back to the CPU engine");
+            globals.engine = EngineKind::Cpu;
+        }
+
+        // The native kernel needs f64 in shaders and profiling needs timestamps, both optional
+        let optional = wgpu::Features::SHADER_F64 | wgpu::Features::TIMESTAMP_QUERY;
+        let features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+            | (adapter.features() & optional);
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
+                    features,
                     limits: wgpu::Limits::default(),
                     label: None,
                 },
                 None,
             )
             .await
-            .unwrap();
+            .map_err(|e| e.to_string())?;
+
+        globals.negotiate(device.features());
+
+        let (device, queue) = (Arc::new(device), Arc::new(queue));
 
         let surface_caps = surface.get_capabilities(&adapter);
 
@@ -169,16 +435,29 @@ impl State {
         surface.configure(&device, &config);
 
         let pipelines = Pipelines {
-            compute: pipeline::compute::ComputePipeline::new(&device),
+            histogram: pipeline::histogram::HistogramPipeline::new(&device),
+            colouring: pipeline::colouring::ColouringPipeline::new(&device),
             render: pipeline::render::RenderPipeline::new(&device, surface_format),
         };
 
+        let (output_texture, output_view) = create_output_texture(&device, size.width, size.height);
+        let samples = sampling::jittered(globals.supersampling);
+        let buffers = engine::Buffers::new(
+            &device,
+            size.width,
+            size.height,
+            samples.len(),
+            globals.refine,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+        let palette_texture = create_palette_texture(&device, &queue, &globals.palette);
+
         let render_data = RenderData {
             vertex_buffer: {
                 let bytes = bytemuck::cast_slice(QUAD_VERTICIES);
                 device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("Vertex Buffer"),
-                    contents: &bytes,
+                    contents: bytes,
                     usage: wgpu::BufferUsages::VERTEX,
                 })
             },
@@ -186,57 +465,29 @@ impl State {
                 let bytes = bytemuck::cast_slice(QUAD_INDICES);
                 device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
                     label: Some("Index Buffer"),
-                    contents: &bytes,
+                    contents: bytes,
                     usage: wgpu::BufferUsages::INDEX,
                 })
             },
+            colouring_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Colouring globals buffer"),
+                contents: &storage::Uniform(&globals.colouring_globals(size.height)).to_bytes(),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            }),
+            buffers,
+            sample_buffer: create_sample_buffer(&device, &samples),
+            samples,
+            output_texture,
+            output_view,
+            palette_view: palette_texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            palette_texture,
+            palette: globals.palette.clone(),
+            histogram: HistogramData::new(&device),
+            placeholder: Placeholder::new(&device, size.width, size.height),
+            profiler: Profiler::new(&device, &queue),
         };
 
-        let compute_data = ComputeData {
-            globals_buffer: {
-                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Globals buffer"),
-                    contents: {
-                        let storage: storage::Globals = (&globals).into();
-                        let uniform = storage::Uniform(&storage);
-                        &uniform.into_bytes()
-                    },
-                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-                })
-            },
-            orbit_buffer: {
-                let z = globals.z0.clone();
-                let c = globals.reference.clone();
-                let r = globals.radius.clone();
-                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Reference orbits buffer"),
-                    contents: {
-                        let p = mandelbrot::compute_reference_orbit::<ITERATIONS>(z, c, r);
-                        let buffer = storage::Buffer(&p);
-                        &buffer.into_bytes()
-                    },
-                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-                })
-            },
-            render_texture: {
-                let texture = device.create_texture(&wgpu::TextureDescriptor {
-                    label: Some("Output texture"),
-                    size: wgpu::Extent3d {
-                        width: size.width,
-                        height: size.height,
-                        depth_or_array_layers: 1,
-                    },
-                    mip_level_count: 1,
-                    sample_count: 1,
-                    dimension: wgpu::TextureDimension::D2,
-                    format: wgpu::TextureFormat::Rgba32Float,
-                    usage: wgpu::TextureUsages::STORAGE_BINDING,
-                    view_formats: &[wgpu::TextureFormat::Rgba32Float],
-                });
-
-                texture.create_view(&wgpu::TextureViewDescriptor::default())
-            },
-        };
+        let engine = create_engine(globals.engine, &device, &queue);
 
         let gui_layer = {
             let ctx = egui::Context::default();
@@ -252,7 +503,7 @@ impl State {
             }
         };
 
-        Self {
+        Ok(Self {
             globals,
             surface,
             device,
@@ -261,15 +512,39 @@ impl State {
             size,
             pipelines,
             render_data,
-            compute_data,
             gui_layer,
-        }
+            engine,
+            last_navigation: None,
+            rendered: Rendered::default(),
+            repaint_at: None,
+            pan_remainder: glam::f32::Vec2::ZERO,
+        })
     }
 
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
         self.size
     }
 
+    // Size of the render target, following the window size and the render scale
+    fn target_size(&self) -> (u32, u32) {
+        let navigating = self
+            .last_navigation
+            .is_some_and(|t| t.elapsed().as_secs_f32() < NAVIGATION_SETTLE_TIME);
+
+        let mut scale = self
+            .globals
+            .render_scale
+            .clamp(MIN_RENDER_SCALE, MAX_RENDER_SCALE);
+
+        if self.globals.dynamic_resolution && navigating {
+            scale = (scale * NAVIGATION_SCALE).max(MIN_RENDER_SCALE);
+        }
+
+        let width = ((self.size.width as f32 * scale).round() as u32).max(1);
+        let height = ((self.size.height as f32 * scale).round() as u32).max(1);
+        (width, height)
+    }
+
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         if new_size.width > 0
             && new_size.height > 0
@@ -283,7 +558,33 @@ impl State {
         }
     }
 
+    // When the next frame should be drawn, or None when nothing is changing
+    pub fn next_redraw(&self) -> Option<std::time::Instant> {
+        let now = std::time::Instant::now();
+        let (width, height) = self.target_size();
+        let parameters = self.globals.view_parameters(width, height);
+        let dirty = self.rendered.dirty(&parameters);
+
+        if dirty || self.engine.progress() < 1.0 || self.render_data.histogram.stale {
+            return Some(now);
+        }
+
+        // Full resolution is restored once navigation has settled
+        let settled = self
+            .last_navigation
+            .filter(|_| self.globals.dynamic_resolution)
+            .map(|t| t + std::time::Duration::from_secs_f32(NAVIGATION_SETTLE_TIME))
+            .filter(|t| *t > now);
+
+        match (self.repaint_at, settled) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+
     pub fn input(&mut self, event: &WindowEvent) -> bool {
+        self.repaint_at = Some(std::time::Instant::now());
+
         let mut handled = self
             .gui_layer
             .state
@@ -295,15 +596,13 @@ impl State {
                 input:
                     winit::event::KeyboardInput {
                         virtual_keycode: Some(winit::event::VirtualKeyCode::Space),
-                        state,
+                        state: winit::event::ElementState::Released,
                         ..
                     },
                 ..
             } => {
-                if *state == winit::event::ElementState::Released {
-                    self.gui_layer.enabled = !self.gui_layer.enabled;
-                    handled = true;
-                }
+                self.gui_layer.enabled = !self.gui_layer.enabled;
+                handled = true;
             }
             // WindowEvent::MouseWheel { delta, phase, .. } => match &phase {
             //     winit::event::TouchPhase::Moved => {
@@ -326,8 +625,13 @@ impl State {
         handled
     }
 
+    // The reference orbit stays in place while panning so the engine can reuse pixels, and only
+    // moves to the center once it drifts too far away
     pub fn update(&mut self) {
-        self.globals.reference = self.globals.center.clone();
+        let offset = (self.globals.reference.clone() - &self.globals.center).abs();
+        if *offset.real() > self.globals.scale() * REFERENCE_DRIFT {
+            self.globals.reference = self.globals.center.clone();
+        }
     }
 
     pub fn render(&mut self, window: &Window) -> Result<(), wgpu::SurfaceError> {
@@ -343,7 +647,19 @@ impl State {
             self.globals.timing.last_checkpoint = std::time::Instant::now();
         }
 
+        self.repaint_at = None;
+        if let Some((counts, statistics)) = self.render_data.histogram.poll(&self.device) {
+            self.globals.histogram = counts;
+            self.globals.statistics = statistics;
+            self.globals.adjust_iterations(&statistics);
+        }
+
+        if let Some(times) = self.render_data.profiler.poll(&self.device) {
+            profiler::record(&mut self.globals.timing.frames, times);
+        }
+
         let output = self.surface.get_current_texture()?;
+        self.render_data.profiler.begin_frame();
         let view = output
             .texture
             .create_view(&wgpu::TextureViewDescriptor::default());
@@ -356,90 +672,254 @@ impl State {
 
         let mut cmd_buffer = Vec::new();
 
-        // Copy globals to GPU
-        {
-            let bytes = {
-                let storage: storage::Globals = (&self.globals).into();
-                storage::Uniform(&storage).into_bytes()
-            };
+        if self.engine.kind() != self.globals.engine {
+            self.engine = create_engine(self.globals.engine, &self.device, &self.queue);
+            self.rendered = Rendered::default();
+        }
 
-            let globals_buffer =
-                self.device
-                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                        label: None,
-                        contents: &bytes,
-                        usage: wgpu::BufferUsages::COPY_SRC,
-                    });
+        // Recreate the render targets when the window or the render scale changed
+        let (width, height) = self.target_size();
+        let texture = &self.render_data.output_texture;
+        let resized = (texture.width(), texture.height()) != (width, height);
+        if resized {
+            let (texture, view) = create_output_texture(&self.device, width, height);
+            self.render_data.output_texture = texture;
+            self.render_data.output_view = view;
+        }
 
-            encoder.copy_buffer_to_buffer(
-                &globals_buffer,
-                0,
-                &self.compute_data.globals_buffer,
-                0,
-                bytes.len() as wgpu::BufferAddress,
+        let samples = sampling::jittered(self.globals.supersampling);
+        let (planes, refine) = (samples.len(), self.globals.refine);
+        if !self
+            .render_data
+            .buffers
+            .matches(width, height, planes, refine)
+            || samples != self.render_data.samples
+        {
+            self.render_data.buffers = engine::Buffers::new(
+                &self.device,
+                width,
+                height,
+                planes,
+                refine,
+                wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
             );
+            self.render_data.sample_buffer = create_sample_buffer(&self.device, &samples);
+            self.render_data.samples = samples;
         }
 
-        // Copy orbit buffer to GPU
-        {
-            let bytes = {
-                let z = self.globals.z0.clone();
-                let c = self.globals.reference.clone();
-                let r = self.globals.radius.clone();
-                let p = mandelbrot::compute_reference_orbit::<ITERATIONS>(z, c, r);
-                let buffer = storage::Buffer(&p);
-                &buffer.into_bytes()
-            };
+        // Compute pass, only when the view changed or the engine is still working on it
+        // Building a view computes its reference orbits
+        let start = std::time::Instant::now();
+        let dirty = self.rendered.update(&self.globals, width, height);
+        if dirty {
+            let elapsed = start.elapsed().as_secs_f32() * 1e3;
+            self.render_data.profiler.set_orbit_time(elapsed);
+        }
 
-            let orbit_buffer = self
-                .device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: None,
-                    contents: &bytes,
-                    usage: wgpu::BufferUsages::COPY_SRC,
-                });
+        let rendering = self.rendered.render(
+            self.engine.as_mut(),
+            dirty,
+            engine::Target::Buffer {
+                buffers: &self.render_data.buffers,
+                queue: &self.queue,
+                encoder: &mut encoder,
+                profiler: &self.render_data.profiler,
+            },
+        );
 
-            encoder.copy_buffer_to_buffer(
-                &orbit_buffer,
-                0,
-                &self.compute_data.orbit_buffer,
+        // The placeholder maps the view onto the last complete image
+        let parameters = self.globals.view_parameters(width, height);
+
+        let complete = self.engine.progress() >= 1.0;
+        let placeholder = &self.render_data.placeholder;
+        let reprojection = match &placeholder.parameters {
+            Some(shown) => parameters.reprojection(shown),
+            None => storage::Reprojection {
+                scale: glam::f32::Vec2::ONE,
+                offset: glam::f32::Vec2::ZERO,
+            },
+        };
+
+        self.queue.write_buffer(
+            &placeholder.reprojection_buffer,
+            0,
+            &storage::Uniform(&reprojection).to_bytes(),
+        );
+
+        // Histogram pass, whenever the channels may have changed or their statistics are yet to be
+        // read back
+        let histogram_data = &mut self.render_data.histogram;
+        histogram_data.stale |= rendering;
+        let read_histogram = histogram_data.stale && histogram_data.readback.is_none();
+        if rendering || read_histogram {
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Histogram, false);
+            encoder.clear_buffer(&histogram_data.counts_buffer, 0, None);
+
+            // Escape iterations are counted in whole iterations of the orbits being rendered
+            let iterations = self.rendered.view().map_or(0, engine::View::iterations);
+            self.queue.write_buffer(
+                &histogram_data.statistics_buffer,
                 0,
-                bytes.len() as wgpu::BufferAddress,
+                bytemuck::cast_slice(&statistics::initial_words(iterations)),
             );
+
+            let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("Histogram bind group"),
+                layout: &self.pipelines.histogram.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: self.render_data.buffers.channels.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: histogram_data.counts_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: histogram_data.cdf_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
+                        resource: histogram_data.statistics_buffer.as_entire_binding(),
+                    },
+                ],
+            });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Histogram pass"),
+                });
+
+                // Every invocation strides over the pixels, so a few workgroups per bin suffice
+                let pixels = width * height;
+                let workgroups = pixels
+                    .div_ceil(histogram::BINS as u32)
+                    .min(HISTOGRAM_WORKGROUPS);
+                compute_pass.set_bind_group(0, &bind_group, &[]);
+                compute_pass.set_pipeline(&self.pipelines.histogram.accumulate_pipeline);
+                compute_pass.dispatch_workgroups(workgroups, 1, 1);
+                compute_pass.set_pipeline(&self.pipelines.histogram.cumulate_pipeline);
+                compute_pass.dispatch_workgroups(1, 1, 1);
+            }
+
+            if read_histogram {
+                encoder.copy_buffer_to_buffer(
+                    &histogram_data.counts_buffer,
+                    0,
+                    &histogram_data.readback_buffer,
+                    0,
+                    histogram_data.counts_buffer.size(),
+                );
+
+                encoder.copy_buffer_to_buffer(
+                    &histogram_data.statistics_buffer,
+                    0,
+                    &histogram_data.readback_buffer,
+                    histogram_data.counts_buffer.size(),
+                    histogram_data.statistics_buffer.size(),
+                );
+            }
+
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Histogram, true);
         }
 
-        // Compute pass
+        // Colouring pass, cheap enough to run every frame
         {
+            if self.render_data.palette != self.globals.palette {
+                self.render_data.palette = self.globals.palette.clone();
+                write_palette(
+                    &self.queue,
+                    &self.render_data.palette_texture,
+                    &self.render_data.palette,
+                );
+            }
+
+            self.queue.write_buffer(
+                &self.render_data.colouring_buffer,
+                0,
+                &storage::Uniform(&self.globals.colouring_globals(height)).to_bytes(),
+            );
+
             let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: Some("Compute bind group"),
-                layout: &self.pipelines.compute.bind_group_layout,
+                label: Some("Colouring bind group"),
+                layout: &self.pipelines.colouring.bind_group_layout,
                 entries: &[
                     wgpu::BindGroupEntry {
                         binding: 0,
-                        resource: self.compute_data.globals_buffer.as_entire_binding(),
+                        resource: self.render_data.colouring_buffer.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 1,
-                        resource: self.compute_data.orbit_buffer.as_entire_binding(),
+                        resource: self.render_data.buffers.channels.as_entire_binding(),
                     },
                     wgpu::BindGroupEntry {
                         binding: 2,
+                        resource: wgpu::BindingResource::TextureView(&self.render_data.output_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 3,
                         resource: wgpu::BindingResource::TextureView(
-                            &self.compute_data.render_texture,
+                            &self.render_data.palette_view,
                         ),
                     },
+                    wgpu::BindGroupEntry {
+                        binding: 4,
+                        resource: self.render_data.histogram.cdf_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 5,
+                        resource: self.render_data.sample_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 6,
+                        resource: self.render_data.buffers.refinement.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: self.render_data.buffers.refined.as_entire_binding(),
+                    },
                 ],
             });
 
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Colouring, false);
             {
                 let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
-                    label: Some("Compute pass"),
+                    label: Some("Colouring pass"),
                 });
 
-                compute_pass.set_pipeline(&self.pipelines.compute.pipeline);
+                // Must be kept in sync with the workgroup size in colouring.wgsl
+                compute_pass.set_pipeline(&self.pipelines.colouring.pipeline);
                 compute_pass.set_bind_group(0, &bind_group, &[]);
-                compute_pass.dispatch_workgroups(self.size.width, self.size.height, 1)
+                compute_pass.dispatch_workgroups(width.div_ceil(8), height.div_ceil(8), 1);
             }
+
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Colouring, true);
+        }
+
+        // Keeps the image once it is complete, to stand in for the next view
+        let placeholder = &mut self.render_data.placeholder;
+        if complete && placeholder.parameters.as_ref() != Some(&parameters) {
+            let texture = &self.render_data.output_texture;
+            if placeholder.texture.size() != texture.size() {
+                *placeholder = Placeholder::new(&self.device, width, height);
+            }
+
+            encoder.copy_texture_to_texture(
+                texture.as_image_copy(),
+                placeholder.texture.as_image_copy(),
+                texture.size(),
+            );
+
+            placeholder.parameters = Some(parameters);
         }
 
         // Render pass
@@ -447,12 +927,31 @@ impl State {
             let bind_group = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
                 label: Some("Render bind group"),
                 layout: &self.pipelines.render.bind_group_layout,
-                entries: &[wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&self.compute_data.render_texture),
-                }],
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(&self.render_data.output_view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: wgpu::BindingResource::TextureView(
+                            &self.render_data.placeholder.view,
+                        ),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 2,
+                        resource: self
+                            .render_data
+                            .placeholder
+                            .reprojection_buffer
+                            .as_entire_binding(),
+                    },
+                ],
             });
 
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Render, false);
             {
                 let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                     label: Some("Render Pass"),
@@ -485,35 +984,55 @@ impl State {
 
                 render_pass.draw_indexed(0..6, 0, 0..1);
             }
+
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Render, true);
         }
 
         // GUI Pass
         if self.gui_layer.enabled {
             let screen_descriptor = egui_wgpu::renderer::ScreenDescriptor {
-                size_in_pixels: [self.size.width as u32, self.size.height as u32],
+                size_in_pixels: [self.size.width, self.size.height],
                 pixels_per_point: self.gui_layer.state.pixels_per_point(),
             };
 
             let input = self.gui_layer.state.take_egui_input(window);
             let output = self.gui_layer.ctx.run(input, |ctx| {
-                let input = self.gui_layer.interface.ui(ctx, &mut self.globals);
+                let input =
+                    self.gui_layer
+                        .interface
+                        .ui(ctx, &mut self.globals, self.engine.as_ref());
 
-                match input.mouse_scroll {
-                    Some(delta) => {
-                        self.globals.zoom += delta * 0.01;
-                    }
-                    _ => {}
+                if input.mouse_scroll.is_some() || input.mouse_drag.is_some() {
+                    self.last_navigation = Some(std::time::Instant::now());
+                }
+
+                if let Some(delta) = input.mouse_scroll {
+                    self.globals.zoom += delta * 0.01;
+                }
+
+                // Pans by whole pixels of the render target, so the engine can reuse the image
+                if let Some(delta) = input.mouse_drag {
+                    let pixels = self.pan_remainder
+                        + glam::f32::vec2(-delta.x, delta.y) * 0.001 * height as f32;
+                    let whole = pixels.round();
+                    self.pan_remainder = pixels - whole;
+
+                    let delta = rug::Complex::with_val(PRECISION, (whole.x, whole.y));
+                    self.globals.center += delta * (self.globals.scale() / height);
                 }
 
-                match input.mouse_drag {
-                    Some(delta) => {
-                        let delta = rug::Complex::with_val(PRECISION, (-delta.x, delta.y)) * 0.001;
-                        self.globals.center += delta * self.globals.zoom.clone().exp().recip();
+                if let Some((index, uv)) = input.place_trap {
+                    let position = self.globals.screen_to_plane(uv, self.size);
+                    if let Some(trap) = self.globals.traps.get_mut(index) {
+                        trap.position = position;
                     }
-                    _ => {}
                 }
             });
 
+            self.repaint_at = std::time::Instant::now().checked_add(output.repaint_after);
+
             self.gui_layer.state.handle_platform_output(
                 window,
                 &self.gui_layer.ctx,
@@ -541,24 +1060,37 @@ impl State {
                 &screen_descriptor,
             );
 
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("GUI Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Load,
-                        store: true,
-                    },
-                })],
-                depth_stencil_attachment: None,
-            });
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Gui, false);
+            {
+                let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("GUI Pass"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Load,
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+
+                self.gui_layer
+                    .renderer
+                    .render(&mut render_pass, &paint_jobs, &screen_descriptor);
+            }
 
-            self.gui_layer
-                .renderer
-                .render(&mut render_pass, &paint_jobs, &screen_descriptor);
+            self.render_data
+                .profiler
+                .stamp(&mut encoder, Stage::Gui, true);
+            cmd_buffer.extend(gui_commands);
+        }
 
-            cmd_buffer.extend(gui_commands.into_iter());
+        // Frames without timestamps are done, the others once their readback arrives
+        if let Some(times) = self.render_data.profiler.end_frame(&mut encoder) {
+            profiler::record(&mut self.globals.timing.frames, times);
         }
 
         // submit will accept anything that implements IntoIter
@@ -569,10 +1101,463 @@ impl State {
         );
         output.present();
 
+        if read_histogram {
+            self.render_data.histogram.request_readback();
+        }
+
+        self.render_data.profiler.request_readback();
+
         Ok(())
     }
 }
 
+impl Placeholder {
+    fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Placeholder texture"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba32Float,
+            usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[wgpu::TextureFormat::Rgba32Float],
+        });
+
+        let reprojection_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reprojection buffer"),
+            size: storage::Reprojection::min_size().get(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            reprojection_buffer,
+            parameters: None,
+        }
+    }
+}
+
+impl HistogramData {
+    fn new(device: &wgpu::Device) -> Self {
+        let word = std::mem::size_of::<u32>() as wgpu::BufferAddress;
+        let buffer = |label, size, usage| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        Self {
+            counts_buffer: buffer(
+                "Histogram buffer",
+                histogram::BINS as u64 * word,
+                wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            ),
+            cdf_buffer: buffer(
+                "Cumulative distribution buffer",
+                histogram::BINS as u64 * word,
+                wgpu::BufferUsages::STORAGE,
+            ),
+            statistics_buffer: buffer(
+                "Statistics buffer",
+                statistics::WORDS as u64 * word,
+                wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_SRC
+                    | wgpu::BufferUsages::COPY_DST,
+            ),
+            readback_buffer: buffer(
+                "Histogram readback buffer",
+                (histogram::BINS + statistics::WORDS) as u64 * word,
+                wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            ),
+            readback: None,
+            stale: false,
+        }
+    }
+
+    // Starts mapping the readback buffer once the copy into it has been submitted
+    fn request_readback(&mut self) {
+        let mapped = Arc::new(AtomicBool::new(false));
+        let callback_mapped = mapped.clone();
+        self.readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                callback_mapped.store(result.is_ok(), Ordering::Release);
+            });
+
+        self.readback = Some(mapped);
+        self.stale = false;
+    }
+
+    // Returns the bin counts and statistics once the readback has finished, without blocking
+    fn poll(&mut self, device: &wgpu::Device) -> Option<(Vec<u32>, Statistics)> {
+        device.poll(wgpu::Maintain::Poll);
+        if !self.readback.as_ref()?.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let mut counts: Vec<u32> = {
+            let bytes = self.readback_buffer.slice(..).get_mapped_range();
+            bytemuck::pod_collect_to_vec(&bytes)
+        };
+
+        self.readback_buffer.unmap();
+        self.readback = None;
+        let statistics = Statistics::from_words(&counts.split_off(histogram::BINS));
+        Some((counts, statistics))
+    }
+}
+
+fn create_output_texture(
+    device: &wgpu::Device,
+    width: u32,
+    height: u32,
+) -> (wgpu::Texture, wgpu::TextureView) {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Output texture"),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::STORAGE_BINDING | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[wgpu::TextureFormat::Rgba32Float],
+    });
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+    (texture, view)
+}
+
+fn create_palette_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    palette: &Palette,
+) -> wgpu::Texture {
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Palette texture"),
+        size: wgpu::Extent3d {
+            width: palette::LUT_SIZE as u32,
+            height: 1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D1,
+        format: wgpu::TextureFormat::Rgba32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[wgpu::TextureFormat::Rgba32Float],
+    });
+
+    write_palette(queue, &texture, palette);
+    texture
+}
+
+fn write_palette(queue: &wgpu::Queue, texture: &wgpu::Texture, palette: &Palette) {
+    let texels: Vec<f32> = palette.lut().iter().flat_map(|c| c.to_array()).collect();
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(texture.width() * 16),
+            rows_per_image: None,
+        },
+        texture.size(),
+    );
+}
+
+fn create_sample_buffer(device: &wgpu::Device, samples: &[glam::f32::Vec2]) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Sample offsets buffer"),
+        contents: &storage::Array(samples).to_bytes(),
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+pub fn create_engine(
+    kind: EngineKind,
+    device: &Arc<wgpu::Device>,
+    queue: &Arc<wgpu::Queue>,
+) -> Box<dyn Engine> {
+    match kind {
+        EngineKind::Gpu => Box::new(GpuEngine::new(device.clone(), queue.clone())),
+        EngineKind::Cpu => Box::new(CpuEngine::new()),
+    }
+}
+
+impl Globals {
+    pub fn new(center: rug::Complex, zoom: rug::Float) -> Self {
+        Self {
+            timing: {
+                let now = std::time::Instant::now();
+                Timing {
+                    time: 0.0,
+                    avs_fps: 0.0,
+                    reference_time: now,
+                    last_checkpoint: now,
+                    frames_since_last_checkpoint: 0,
+                    frames: VecDeque::with_capacity(profiler::HISTORY),
+                }
+            },
+            z0: rug::Complex::with_val(PRECISION, (0.0, 0.0)),
+            zoom,
+            radius: rug::Float::with_val(PRECISION, 2.0),
+            reference: center.clone(),
+            center,
+            colouring: Colouring::Iterations,
+            equalize: false,
+            palette: Palette::default(),
+            histogram: vec![0; histogram::BINS],
+            statistics: Statistics::default(),
+            stripe_density: 5.0,
+            traps: Vec::new(),
+            subdivision: false,
+            show_subdivision: false,
+            progressive: true,
+            supersampling: 1,
+            reconstruction: Reconstruction::Box,
+            refine: false,
+            refine_threshold: refinement::DEFAULT_THRESHOLD,
+            show_refinement: false,
+            render_scale: 1.0,
+            dynamic_resolution: false,
+            engine: EngineKind::Gpu,
+            projection: Projection::Flat,
+            precision: Precision::Single,
+            reference_grid: 1,
+            iterations: ITERATIONS,
+            auto_iterations: false,
+            shader_f64: false,
+            timestamps: false,
+        }
+    }
+
+    // Records whether the device runs the native kernel, and switches to it when it does
+    pub fn negotiate(&mut self, features: wgpu::Features) {
+        self.shader_f64 = features.contains(wgpu::Features::SHADER_F64);
+        self.timestamps = features.contains(wgpu::Features::TIMESTAMP_QUERY);
+        if self.shader_f64 {
+            self.precision = Precision::Native;
+        }
+    }
+
+    // The arithmetic the engine actually runs, as only the GPU engine has a native kernel and
+    // only on devices with f64 support. Emulation is the next best thing elsewhere.
+    pub fn numeric_path(&self) -> Precision {
+        let native = self.shader_f64 && self.engine == EngineKind::Gpu;
+        if self.precision == Precision::Native && !native {
+            Precision::Emulated
+        } else {
+            self.precision
+        }
+    }
+
+    // Moves the iteration limit in the automatic mode, after the statistics of a frame rendered
+    // with the current one. Raising it takes precedence, as samples cut off at the limit hide
+    // how far escapes really go.
+    pub fn adjust_iterations(&mut self, statistics: &Statistics) {
+        let current = statistics.iterations as usize == self.iterations;
+        if !self.auto_iterations || !current || statistics.samples() == 0 {
+            return;
+        }
+
+        let unresolved = statistics.max_iteration as f32 / statistics.samples() as f32;
+        let limit = match statistics.escape_range {
+            _ if unresolved > RAISE_FRACTION => self.iterations * RAISE_FACTOR,
+            Some((_, last)) if last as usize * LOWER_FACTOR < self.iterations => {
+                last as usize * HEADROOM
+            }
+            _ => self.iterations,
+        };
+
+        self.iterations = limit.clamp(MIN_ITERATIONS, MAX_ITERATIONS);
+    }
+
+    // Starting points of the references on a grid around the primary one, with their orbits.
+    // References that escape are of no use past that point and are left out. When every one does,
+    // the one lasting longest is used alone, and samples outliving it are flagged glitched. Orbits
+    // stop at their escape, so those cost little.
+    fn reference_orbits(&self, width: u32, height: u32) -> Vec<(rug::Complex, mandelbrot::Orbit)> {
+        let double = self.numeric_path() == Precision::Native;
+        let orbit = |c: &rug::Complex| {
+            let z = self.z0.clone();
+            let r = self.radius.clone();
+            mandelbrot::compute_reference_orbit(z, c.clone(), r, self.iterations, double)
+        };
+
+        let extent = reference::extent(width, height, self.projection);
+        let scale = self.scale();
+        let (kept, escaped): (Vec<_>, Vec<_>) = reference::grid(self.reference_grid, extent)
+            .into_iter()
+            .map(|offset| {
+                let offset = rug::Complex::with_val(PRECISION, (offset.x, offset.y)) * &scale;
+                self.reference.clone() + offset
+            })
+            .map(|c| {
+                let orbit = orbit(&c);
+                (c, orbit)
+            })
+            .partition(|(_, orbit)| orbit.escaped.is_none());
+
+        if !kept.is_empty() {
+            return kept;
+        }
+
+        let primary = (self.reference.clone(), orbit(&self.reference));
+        let longest = escaped
+            .into_iter()
+            .chain([primary])
+            .max_by_key(|(_, orbit)| orbit.escaped.unwrap_or(usize::MAX));
+        longest.into_iter().collect()
+    }
+
+    pub fn view(&self, width: u32, height: u32) -> engine::View {
+        let references = self.reference_orbits(width, height);
+        let scale = self.scale();
+        let offsets: Vec<rug::Complex> = references
+            .iter()
+            .map(|(c, _)| c.clone() - &self.center)
+            .collect();
+
+        engine::View {
+            width,
+            height,
+            globals: self.into(),
+            orbit: references
+                .iter()
+                .flat_map(|(_, orbit)| &orbit.points)
+                .copied()
+                .collect(),
+            orbit_f64: references
+                .iter()
+                .flat_map(|(_, orbit)| &orbit.points_f64)
+                .copied()
+                .collect(),
+            native_globals: if self.numeric_path() == Precision::Native {
+                let offsets: Vec<glam::f64::DVec2> = offsets
+                    .iter()
+                    .map(|offset| glam::f64::dvec2(offset.real().to_f64(), offset.imag().to_f64()))
+                    .collect();
+
+                reference::native_uniform(scale.to_f64(), &offsets)
+            } else {
+                Vec::new()
+            },
+            references: offsets
+                .iter()
+                .map(|offset| {
+                    let [x, y] = [offset.real(), offset.imag()].map(Df64::from_float);
+                    storage::Reference {
+                        offset: glam::f32::vec2(x.hi, y.hi),
+                        offset_lo: glam::f32::vec2(x.lo, y.lo),
+                    }
+                })
+                .collect(),
+            reference_map: {
+                let offsets: Vec<glam::f32::Vec2> = offsets
+                    .iter()
+                    .map(|offset| {
+                        let offset = offset.clone() / &scale;
+                        glam::f32::vec2(offset.real().to_f32(), offset.imag().to_f32())
+                    })
+                    .collect();
+
+                reference::assign(width, height, self.projection, &offsets)
+            },
+            traps: self
+                .traps
+                .iter()
+                .take(trap::MAX_TRAPS)
+                .map(|t| t.into())
+                .collect(),
+            subdivide: self.subdivision,
+            samples: sampling::jittered(self.supersampling),
+            refine: self.refine,
+            progressive: self.progressive,
+            shift: None,
+        }
+    }
+
+    fn view_parameters(&self, width: u32, height: u32) -> ViewParameters {
+        ViewParameters {
+            width,
+            height,
+            zoom: self.zoom.clone(),
+            radius: self.radius.clone(),
+            center: self.center.clone(),
+            reference: self.reference.clone(),
+            z0: self.z0.clone(),
+            stripe_density: self.stripe_density,
+            traps: self.traps.clone(),
+            subdivision: self.subdivision,
+            supersampling: self.supersampling,
+            refine: self.refine,
+            refine_threshold: self.refine_threshold,
+            progressive: self.progressive,
+            projection: self.projection,
+            precision: self.numeric_path(),
+            reference_grid: self.reference_grid,
+            iterations: self.iterations,
+        }
+    }
+
+    // Settings of the colouring pass for a render target of the given height
+    pub fn colouring_globals(&self, height: u32) -> storage::ColouringGlobals {
+        storage::ColouringGlobals {
+            colouring: self.colouring as u32,
+            equalize: self.equalize as u32,
+            reconstruction: self.reconstruction as u32,
+            show_subdivision: self.show_subdivision as u32,
+            show_refinement: self.show_refinement as u32,
+            pixel_size: self.scale().to_f32() / height as f32,
+            palette_repeat: self.palette.repeat,
+            palette_offset: self.palette.offset,
+            interior: self.palette.interior,
+        }
+    }
+
+    pub fn scale(&self) -> rug::Float {
+        self.zoom.clone().exp().recip()
+    }
+
+    // Maps a normalized screen position (origin in the top left) onto the complex plane
+    pub fn screen_to_plane(
+        &self,
+        uv: glam::f32::Vec2,
+        size: winit::dpi::PhysicalSize<u32>,
+    ) -> glam::f32::Vec2 {
+        let uv = glam::f32::vec2(uv.x, 1.0 - uv.y);
+        let offset = match self.projection {
+            Projection::Flat => {
+                let aspect_ratio = glam::f32::vec2(size.width as f32 / size.height as f32, 1.0);
+                aspect_ratio * (uv - 0.5)
+            }
+            Projection::Exponential => {
+                let position = uv * glam::f32::vec2(size.width as f32, size.height as f32);
+                exponential::offset(position, size.width, size.height)
+            }
+        };
+
+        let center = glam::f32::vec2(self.center.real().to_f32(), self.center.imag().to_f32());
+        center + self.scale().to_f32() * offset
+    }
+}
+
 impl From<&Globals> for storage::Globals {
     fn from(globals: &Globals) -> Self {
         let z = globals.z0.clone();
@@ -583,23 +1568,186 @@ impl From<&Globals> for storage::Globals {
             0, // Why aren't we using ITERATIONS here?
         );
 
+        let scale = Df64::from_float(&globals.scale());
+
         Self {
             time: globals.timing.time,
-            scale: globals.zoom.clone().exp().recip().to_f32(),
+            scale: scale.hi,
+            scale_lo: scale.lo,
             radius: globals.radius.to_f32(),
             center: {
                 let x = globals.center.real().to_f32();
                 let y = globals.center.imag().to_f32();
                 glam::f32::vec2(x, y)
             },
-            orbit_offset: {
-                let offset = globals.reference.clone() - globals.center.clone();
-                glam::Vec2 {
-                    x: offset.real().to_f32(),
-                    y: offset.imag().to_f32(),
-                }
-            },
+            stripe_density: globals.stripe_density,
+            refine_threshold: globals.refine_threshold,
+            projection: globals.projection as u32,
+            delta_precision: globals.numeric_path() as u32,
             coefficients: { [_a.xyxy(), _b.xyxy(), _c.xyxy(), _d.xyxy()] },
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_renders_only_when_the_view_changes() {
+        let mut globals = Globals::new(
+            rug::Complex::with_val(PRECISION, (-0.5, 0.0)),
+            rug::Float::with_val(PRECISION, 0.0),
+        );
+        let mut engine = CpuEngine::new();
+        let mut rendered = Rendered::default();
+        let mut frame = engine::Frame::default();
+
+        let dirty = rendered.update(&globals, 16, 8);
+        assert!(dirty);
+        assert!(rendered.render(&mut engine, dirty, engine::Target::Memory(&mut frame)));
+        assert_eq!((frame.width, frame.height), (16, 8));
+
+        // Nothing changed and the engine is done with the view
+        let dirty = rendered.update(&globals, 16, 8);
+        assert!(!dirty);
+        assert!(!rendered.render(&mut engine, dirty, engine::Target::Memory(&mut frame)));
+
+        globals.zoom += 1;
+        let dirty = rendered.update(&globals, 16, 8);
+        assert!(dirty);
+        assert!(rendered.render(&mut engine, dirty, engine::Target::Memory(&mut frame)));
+        assert!(!rendered.update(&globals, 16, 8));
+    }
+
+    #[test]
+    fn test_reprojection() {
+        let center = rug::Complex::with_val(PRECISION, (-0.5, 0.25));
+        let mut globals = Globals::new(center, rug::Float::with_val(PRECISION, 3.0));
+        let before = globals.view_parameters(200, 100);
+        let map = |r: storage::Reprojection, uv: glam::f32::Vec2| uv * r.scale + r.offset;
+
+        let same = before.reprojection(&before);
+        assert_eq!(
+            map(same, glam::f32::vec2(0.2, 0.7)),
+            glam::f32::vec2(0.2, 0.7)
+        );
+
+        // Zooming in by a factor of two shows the middle half of the previous image
+        globals.zoom += std::f64::consts::LN_2;
+        let zoomed = globals.view_parameters(200, 100).reprojection(&before);
+        let corner = map(zoomed, glam::f32::Vec2::ZERO);
+        assert!(corner.abs_diff_eq(glam::f32::vec2(0.25, 0.25), 1e-5));
+
+        // Moving by a quarter of the height moves by an eighth of the width at this aspect ratio
+        globals.zoom -= std::f64::consts::LN_2;
+        globals.center += globals.scale() * 0.25;
+        let moved = globals.view_parameters(200, 100).reprojection(&before);
+        let middle = map(moved, glam::f32::vec2(0.5, 0.5));
+        assert!(middle.abs_diff_eq(glam::f32::vec2(0.625, 0.5), 1e-5));
+    }
+
+    #[test]
+    fn test_numeric_path() {
+        let center = rug::Complex::with_val(PRECISION, (-0.5, 0.25));
+        let mut globals = Globals::new(center, rug::Float::with_val(PRECISION, 3.0));
+        globals.negotiate(wgpu::Features::empty());
+        assert_eq!(globals.numeric_path(), Precision::Single);
+        assert!(globals.view(8, 8).orbit_f64.is_empty());
+
+        globals.negotiate(wgpu::Features::SHADER_F64);
+        assert_eq!(globals.numeric_path(), Precision::Native);
+        assert_eq!(globals.view(8, 8).orbit_f64.len(), ITERATIONS);
+
+        // Neither the CPU engine nor a device without f64 support has a native kernel
+        globals.engine = EngineKind::Cpu;
+        assert_eq!(globals.numeric_path(), Precision::Emulated);
+        globals.engine = EngineKind::Gpu;
+        globals.shader_f64 = false;
+        let view = globals.view(8, 8);
+        assert_eq!(view.globals.delta_precision, Precision::Emulated as u32);
+    }
+
+    #[test]
+    fn test_reference_grid() {
+        // Only the lower left reference of the grid stays bounded around this view
+        let center = rug::Complex::with_val(PRECISION, (-0.1, 0.8));
+        let mut globals = Globals::new(center, rug::Float::with_val(PRECISION, 5.0).ln());
+        globals.reference_grid = 2;
+        let view = globals.view(200, 100);
+        assert_eq!(view.references.len(), 1);
+        assert_eq!(view.orbit.len(), ITERATIONS);
+        assert!(view.reference_map.iter().all(|&i| i == 0));
+        let offset = view.references[0].offset;
+        assert!(offset.abs_diff_eq(glam::f32::vec2(-0.1, -0.05), 1e-6));
+
+        // Down to the primary reference once every other escapes
+        globals.zoom = rug::Float::with_val(PRECISION, 10.0 / 3.0).ln();
+        let view = globals.view(200, 100);
+        assert_eq!(view.references.len(), 1);
+        assert_eq!(view.references[0].offset, glam::f32::Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_auto_iterations() {
+        let center = rug::Complex::with_val(PRECISION, (-0.5, 0.25));
+        let mut globals = Globals::new(center, rug::Float::with_val(PRECISION, 3.0));
+        let statistics = |escaped, max_iteration, last| Statistics {
+            iterations: ITERATIONS as u32,
+            escaped,
+            max_iteration,
+            escape_range: Some((1, last)),
+            ..Statistics::default()
+        };
+
+        // Nothing moves until the automatic mode is on
+        globals.adjust_iterations(&statistics(90, 10, 500));
+        assert_eq!(globals.iterations, ITERATIONS);
+
+        globals.auto_iterations = true;
+        globals.adjust_iterations(&statistics(90, 10, 500));
+        assert_eq!(globals.iterations, 2 * ITERATIONS);
+
+        // Frames rendered with another limit are ignored, and the orbits follow the new one
+        globals.adjust_iterations(&statistics(100, 0, 100));
+        assert_eq!(globals.iterations, 2 * ITERATIONS);
+        assert_eq!(globals.view(8, 8).orbit.len(), 2 * ITERATIONS);
+
+        globals.iterations = ITERATIONS;
+        globals.adjust_iterations(&statistics(100, 0, 100));
+        assert_eq!(globals.iterations, 200);
+        globals.adjust_iterations(&Statistics {
+            iterations: 200,
+            ..statistics(100, 0, 20)
+        });
+        assert_eq!(globals.iterations, MIN_ITERATIONS);
+    }
+
+    #[test]
+    fn test_auto_iterations_settle() {
+        let center = rug::Complex::with_val(PRECISION, (-0.5, 0.25));
+        let mut globals = Globals::new(center, rug::Float::with_val(PRECISION, 3.0));
+        globals.auto_iterations = true;
+
+        // A scene whose samples escape by iteration 300, or get cut off below that
+        let frame = |iterations: usize| Statistics {
+            iterations: iterations as u32,
+            escaped: 90,
+            max_iteration: if iterations < 300 { 10 } else { 0 },
+            escape_range: Some((1, iterations.min(300) as u32)),
+            ..Statistics::default()
+        };
+
+        for start in [MIN_ITERATIONS, 50_000] {
+            globals.iterations = start;
+            for _ in 0..16 {
+                globals.adjust_iterations(&frame(globals.iterations));
+            }
+
+            let settled = globals.iterations;
+            assert!((300..300 * LOWER_FACTOR).contains(&settled));
+            globals.adjust_iterations(&frame(settled));
+            assert_eq!(globals.iterations, settled);
+        }
+    }
+}
@@ -0,0 +1,520 @@
+use std::{
+    io::Write,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use rug::{float::Round, Float};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Classification {
+    Exterior,
+    Interior,
+    Undecided,
+}
+
+pub struct Parameters {
+    pub width: u32,
+    pub height: u32,
+    pub iterations: usize,
+    pub max_period: usize,
+}
+
+pub struct AreaBounds {
+    pub lower: Float,
+    pub upper: Float,
+    // Area proven to be outside of the set, and the area of the whole view
+    pub exterior: Float,
+    pub total: Float,
+}
+
+pub struct CertifiedMap {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Classification>,
+    pub area: AreaBounds,
+}
+
+/*
+    Interval arithmetic
+*/
+
+#[derive(Clone, Debug)]
+struct Interval {
+    lo: Float,
+    hi: Float,
+}
+
+#[derive(Clone, Debug)]
+struct ComplexInterval {
+    re: Interval,
+    im: Interval,
+}
+
+impl Interval {
+    fn new(lo: Float, hi: Float) -> Self {
+        Self { lo, hi }
+    }
+
+    fn point(value: &Float) -> Self {
+        Self::new(value.clone(), value.clone())
+    }
+
+    fn around(value: &Float, radius: &Float) -> Self {
+        let p = value.prec();
+        Self::new(
+            Float::with_val_round(p, value - radius, Round::Down).0,
+            Float::with_val_round(p, value + radius, Round::Up).0,
+        )
+    }
+
+    fn prec(&self) -> u32 {
+        self.lo.prec()
+    }
+
+    fn add(&self, other: &Interval) -> Interval {
+        let p = self.prec();
+        Interval::new(
+            Float::with_val_round(p, &self.lo + &other.lo, Round::Down).0,
+            Float::with_val_round(p, &self.hi + &other.hi, Round::Up).0,
+        )
+    }
+
+    fn sub(&self, other: &Interval) -> Interval {
+        let p = self.prec();
+        Interval::new(
+            Float::with_val_round(p, &self.lo - &other.hi, Round::Down).0,
+            Float::with_val_round(p, &self.hi - &other.lo, Round::Up).0,
+        )
+    }
+
+    fn mul(&self, other: &Interval) -> Interval {
+        let p = self.prec();
+        let pairs = [
+            (&self.lo, &other.lo),
+            (&self.lo, &other.hi),
+            (&self.hi, &other.lo),
+            (&self.hi, &other.hi),
+        ];
+
+        let lo = pairs
+            .iter()
+            .map(|(a, b)| Float::with_val_round(p, *a * *b, Round::Down).0)
+            .reduce(|a, b| a.min(&b))
+            .unwrap();
+
+        let hi = pairs
+            .iter()
+            .map(|(a, b)| Float::with_val_round(p, *a * *b, Round::Up).0)
+            .reduce(|a, b| a.max(&b))
+            .unwrap();
+
+        Interval::new(lo, hi)
+    }
+
+    fn square(&self) -> Interval {
+        let p = self.prec();
+        let mig = self.mig();
+        let mag = self.mag();
+        Interval::new(
+            Float::with_val_round(p, mig.square_ref(), Round::Down).0,
+            Float::with_val_round(p, mag.square_ref(), Round::Up).0,
+        )
+    }
+
+    fn double(&self) -> Interval {
+        // Scaling by a power of two is exact
+        Interval::new(self.lo.clone() * 2u32, self.hi.clone() * 2u32)
+    }
+
+    // Smallest absolute value in the interval
+    fn mig(&self) -> Float {
+        if self.lo > 0 {
+            self.lo.clone()
+        } else if self.hi < 0 {
+            self.hi.clone().abs()
+        } else {
+            Float::new(self.prec())
+        }
+    }
+
+    // Largest absolute value in the interval
+    fn mag(&self) -> Float {
+        self.lo.clone().abs().max(&self.hi.clone().abs())
+    }
+
+    fn is_finite(&self) -> bool {
+        self.lo.is_finite() && self.hi.is_finite()
+    }
+
+    fn contains_strictly(&self, other: &Interval) -> bool {
+        self.lo < other.lo && other.hi < self.hi
+    }
+}
+
+impl ComplexInterval {
+    fn add(&self, other: &ComplexInterval) -> ComplexInterval {
+        ComplexInterval {
+            re: self.re.add(&other.re),
+            im: self.im.add(&other.im),
+        }
+    }
+
+    fn mul(&self, other: &ComplexInterval) -> ComplexInterval {
+        ComplexInterval {
+            re: self.re.mul(&other.re).sub(&self.im.mul(&other.im)),
+            im: self.re.mul(&other.im).add(&self.im.mul(&other.re)),
+        }
+    }
+
+    fn square(&self) -> ComplexInterval {
+        ComplexInterval {
+            re: self.re.square().sub(&self.im.square()),
+            im: self.re.mul(&self.im).double(),
+        }
+    }
+
+    fn abs_sqr_lower(&self) -> Float {
+        let p = self.re.prec();
+        let re = Float::with_val_round(p, self.re.mig().square_ref(), Round::Down).0;
+        let im = Float::with_val_round(p, self.im.mig().square_ref(), Round::Down).0;
+        Float::with_val_round(p, re + im, Round::Down).0
+    }
+
+    fn abs_sqr_upper(&self) -> Float {
+        let p = self.re.prec();
+        let re = Float::with_val_round(p, self.re.mag().square_ref(), Round::Up).0;
+        let im = Float::with_val_round(p, self.im.mag().square_ref(), Round::Up).0;
+        Float::with_val_round(p, re + im, Round::Up).0
+    }
+
+    fn is_finite(&self) -> bool {
+        self.re.is_finite() && self.im.is_finite()
+    }
+
+    fn contains_strictly(&self, other: &ComplexInterval) -> bool {
+        self.re.contains_strictly(&other.re) && self.im.contains_strictly(&other.im)
+    }
+}
+
+/*
+    Classification
+*/
+
+// Every point of `c` escapes if the interval orbit of 0 lies entirely outside the radius 2 disk
+fn proves_exterior(c: &ComplexInterval, iterations: usize) -> bool {
+    let p = c.re.prec();
+    let zero = Interval::point(&Float::new(p));
+    let mut z = ComplexInterval {
+        re: zero.clone(),
+        im: zero,
+    };
+
+    for _ in 0..iterations {
+        z = z.square().add(c);
+        if z.abs_sqr_lower() > 4 {
+            return true;
+        }
+
+        if !z.is_finite() {
+            return false;
+        }
+    }
+
+    false
+}
+
+// Looks for a box `b` such that f_c^p(b) lies strictly inside `b` and |(f_c^p)'| < 1 on `b`, for
+// every point of `c`. Such a box contains an attracting cycle, which places `c` in the interior.
+fn proves_interior(c: &ComplexInterval, iterations: usize, max_period: usize) -> bool {
+    let p = c.re.prec();
+    let c0 = rug::Complex::with_val(
+        p,
+        (
+            Float::with_val(p, &c.re.lo + &c.re.hi) / 2u32,
+            Float::with_val(p, &c.im.lo + &c.im.hi) / 2u32,
+        ),
+    );
+
+    // Let the orbit of the center settle onto its cycle
+    let mut z = rug::Complex::new(p);
+    for _ in 0..iterations {
+        z.square_mut();
+        z += &c0;
+        if z.real().clone().square() + z.imag().clone().square() > 4 {
+            return false;
+        }
+    }
+
+    // Find the smallest period that returns close to the starting point
+    let threshold = Float::with_val(p, z.abs_ref()).max(&Float::with_val(p, 1)) * 1e-6;
+    let mut w = z.clone();
+    let mut cycle = None;
+    for period in 1..=max_period {
+        w.square_mut();
+        w += &c0;
+        let distance = Float::with_val(p, (w.clone() - &z).abs_ref());
+        if distance < threshold {
+            cycle = Some((period, distance));
+            break;
+        }
+    }
+
+    let Some((period, distance)) = cycle else {
+        return false;
+    };
+
+    let pixel_size =
+        Float::with_val(p, &c.re.hi - &c.re.lo).max(&Float::with_val(p, &c.im.hi - &c.im.lo));
+    let mut radius = distance.max(&pixel_size) * 2u32;
+
+    for _ in 0..12 {
+        let b = ComplexInterval {
+            re: Interval::around(z.real(), &radius),
+            im: Interval::around(z.imag(), &radius),
+        };
+
+        let mut image = b.clone();
+        let mut derivative = ComplexInterval {
+            re: Interval::point(&Float::with_val(p, 1)),
+            im: Interval::point(&Float::new(p)),
+        };
+
+        for _ in 0..period {
+            derivative = derivative.mul(&ComplexInterval {
+                re: image.re.double(),
+                im: image.im.double(),
+            });
+            image = image.square().add(c);
+        }
+
+        if image.is_finite() && b.contains_strictly(&image) && derivative.abs_sqr_upper() < 1 {
+            return true;
+        }
+
+        radius *= 4u32;
+    }
+
+    false
+}
+
+fn classify(c: &ComplexInterval, iterations: usize, max_period: usize) -> Classification {
+    if proves_exterior(c, iterations) {
+        Classification::Exterior
+    } else if proves_interior(c, iterations, max_period) {
+        Classification::Interior
+    } else {
+        Classification::Undecided
+    }
+}
+
+/*
+    Rendering
+*/
+
+// Classifies every pixel of the view centered on `center`, `scale` units tall
+pub fn render(center: &rug::Complex, scale: &Float, parameters: &Parameters) -> CertifiedMap {
+    let p = center.prec().0.max(center.prec().1).max(scale.prec());
+    let (width, height) = (parameters.width as usize, parameters.height as usize);
+
+    // Pixel edges are shared between neighbours so the rectangles tile the view exactly
+    let extent_x = Float::with_val(p, scale * width as u32) / height as u32;
+    let left = Float::with_val(p, center.real() - Float::with_val(p, &extent_x / 2u32));
+    let top = Float::with_val(p, center.imag() + Float::with_val(p, scale / 2u32));
+    let edges_x: Vec<Float> = (0..=width)
+        .map(|i| {
+            Float::with_val(
+                p,
+                &left + Float::with_val(p, &extent_x * i as u32) / width as u32,
+            )
+        })
+        .collect();
+    let edges_y: Vec<Float> = (0..=height)
+        .map(|j| {
+            Float::with_val(
+                p,
+                &top - Float::with_val(p, scale * j as u32) / height as u32,
+            )
+        })
+        .collect();
+
+    let next_row = AtomicUsize::new(0);
+    let mut rows: Vec<(usize, Vec<Classification>)> = std::thread::scope(|scope| {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut rows = Vec::new();
+                    loop {
+                        let y = next_row.fetch_add(1, Ordering::Relaxed);
+                        if y >= height {
+                            break rows;
+                        }
+
+                        let im = Interval::new(edges_y[y + 1].clone(), edges_y[y].clone());
+                        let row = (0..width)
+                            .map(|x| {
+                                let c = ComplexInterval {
+                                    re: Interval::new(edges_x[x].clone(), edges_x[x + 1].clone()),
+                                    im: im.clone(),
+                                };
+
+                                classify(&c, parameters.iterations, parameters.max_period)
+                            })
+                            .collect();
+
+                        rows.push((y, row));
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Certified render worker panicked"))
+            .collect()
+    });
+
+    rows.sort_by_key(|(y, _)| *y);
+    let pixels: Vec<Classification> = rows.into_iter().flat_map(|(_, row)| row).collect();
+
+    let area = area_bounds(&pixels, &edges_x, &edges_y);
+
+    CertifiedMap {
+        width: parameters.width,
+        height: parameters.height,
+        pixels,
+        area,
+    }
+}
+
+// Accumulates the areas with outward rounding so that the bounds stay rigorous. The proven areas
+// round every step down, including the sizes of the pixels, and the view as a whole rounds up.
+fn area_bounds(pixels: &[Classification], edges_x: &[Float], edges_y: &[Float]) -> AreaBounds {
+    let p = edges_x[0].prec();
+    let width = edges_x.len() - 1;
+    let height = edges_y.len() - 1;
+    let size = |hi: &Float, lo: &Float, round| Float::with_val_round(p, hi - lo, round).0;
+
+    let mut lower = Float::new(p);
+    let mut exterior = Float::new(p);
+    for (i, classification) in pixels.iter().enumerate() {
+        let (x, y) = (i % width, i / width);
+        let proven = match classification {
+            Classification::Interior => &mut lower,
+            Classification::Exterior => &mut exterior,
+            Classification::Undecided => continue,
+        };
+
+        let w = size(&edges_x[x + 1], &edges_x[x], Round::Down);
+        let h = size(&edges_y[y], &edges_y[y + 1], Round::Down);
+        let area = Float::with_val_round(p, &w * &h, Round::Down).0;
+        *proven = Float::with_val_round(p, &*proven + &area, Round::Down).0;
+    }
+
+    let total = {
+        let w = size(&edges_x[width], &edges_x[0], Round::Up);
+        let h = size(&edges_y[0], &edges_y[height], Round::Up);
+        Float::with_val_round(p, &w * &h, Round::Up).0
+    };
+
+    let upper = Float::with_val_round(p, &total - &exterior, Round::Up).0;
+    AreaBounds {
+        lower,
+        upper,
+        exterior,
+        total,
+    }
+}
+
+impl CertifiedMap {
+    pub fn count(&self, classification: Classification) -> usize {
+        self.pixels.iter().filter(|c| **c == classification).count()
+    }
+
+    // Writes the map as a binary PPM: exterior is white, interior black and undecided red
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let bytes: Vec<u8> = self
+            .pixels
+            .iter()
+            .flat_map(|c| match c {
+                Classification::Exterior => [255, 255, 255],
+                Classification::Interior => [0, 0, 0],
+                Classification::Undecided => [255, 0, 0],
+            })
+            .collect();
+
+        writer.write_all(&bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PRECISION: u32 = 64;
+
+    fn pixel(re: f64, im: f64, size: f64) -> ComplexInterval {
+        let radius = Float::with_val(PRECISION, size / 2.0);
+        ComplexInterval {
+            re: Interval::around(&Float::with_val(PRECISION, re), &radius),
+            im: Interval::around(&Float::with_val(PRECISION, im), &radius),
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify(&pixel(1.0, 0.0, 0.01), 100, 8),
+            Classification::Exterior
+        );
+        assert_eq!(
+            classify(&pixel(-0.1, 0.0, 0.01), 100, 8),
+            Classification::Interior
+        );
+        assert_eq!(
+            classify(&pixel(-1.0, 0.0, 0.01), 100, 8),
+            Classification::Interior
+        );
+    }
+
+    #[test]
+    fn test_area_bounds() {
+        let center = rug::Complex::with_val(PRECISION, (-0.75, 0.0));
+        let scale = Float::with_val(PRECISION, 2.5);
+        let parameters = Parameters {
+            width: 24,
+            height: 20,
+            iterations: 64,
+            max_period: 4,
+        };
+
+        let map = render(&center, &scale, &parameters);
+        assert_eq!(map.pixels.len(), 24 * 20);
+        assert!(map.count(Classification::Interior) > 0);
+        assert!(map.count(Classification::Exterior) > 0);
+
+        // The area of the Mandelbrot set is approximately 1.50659
+        assert!(map.area.lower < 1.50659);
+        assert!(map.area.upper > 1.50659);
+    }
+
+    #[test]
+    fn test_proven_area_within_total() {
+        // Edges exact in 8 bits whose differences are not, against the same edges at full precision
+        let edges = |precision, values: &[f64]| -> Vec<Float> {
+            let edge = |value| Float::with_val(precision, Float::with_val(8, value));
+            values.iter().map(edge).collect()
+        };
+
+        let (edges_x, edges_y) = ([-0.8242, 0.5, 1.3], [1.891, -1.570]);
+        let pixels = [Classification::Interior, Classification::Exterior];
+        let area = area_bounds(&pixels, &edges(8, &edges_x), &edges(8, &edges_y));
+        let exact = area_bounds(&pixels, &edges(256, &edges_x), &edges(256, &edges_y));
+
+        assert!(area.lower <= exact.lower);
+        assert!(area.exterior <= exact.exterior);
+        assert!(area.total >= exact.total);
+        assert!(Float::with_val_round(8, &area.lower + &area.exterior, Round::Up).0 <= area.total);
+    }
+}
@@ -0,0 +1,167 @@
+use glam::f32::{vec2, vec3, Vec2, Vec3, Vec4};
+
+use crate::{engine::Frame, histogram, refinement, sampling::Reconstruction, storage};
+
+// Must be kept in sync with `GAUSSIAN_SIGMA` in colouring.wgsl
+const GAUSSIAN_SIGMA: f32 = 0.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Colouring {
+    Iterations,
+    TrapDistance,
+    TrapIteration,
+    TriangleInequality,
+    Stripe,
+    Curvature,
+    DistanceEstimate,
+}
+
+impl Colouring {
+    pub const ALL: [Colouring; 7] = [
+        Colouring::Iterations,
+        Colouring::TrapDistance,
+        Colouring::TrapIteration,
+        Colouring::TriangleInequality,
+        Colouring::Stripe,
+        Colouring::Curvature,
+        Colouring::DistanceEstimate,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Colouring::Iterations => "Iterations",
+            Colouring::TrapDistance => "Trap distance",
+            Colouring::TrapIteration => "Trap iteration",
+            Colouring::TriangleInequality => "Triangle inequality",
+            Colouring::Stripe => "Stripe average",
+            Colouring::Curvature => "Curvature",
+            Colouring::DistanceEstimate => "Distance estimate",
+        }
+    }
+}
+
+/*
+    CPU mirror of colouring.wgsl, used for frames that never reach the GPU
+*/
+
+fn value(globals: &storage::ColouringGlobals, cdf: &[f32], channels: &storage::Channels) -> f32 {
+    match globals.colouring {
+        1 => (-4.0 * channels.trap_distance).exp(),
+        2 => channels.trap_iteration,
+        3 => channels.triangle_inequality,
+        4 => channels.stripe,
+        5 => channels.curvature,
+        6 => (channels.distance / (4.0 * globals.pixel_size)).clamp(0.0, 1.0),
+        _ if globals.equalize != 0 => histogram::equalize(cdf, channels.iterations),
+        _ => channels.iterations,
+    }
+}
+
+fn is_interior(globals: &storage::ColouringGlobals, channels: &storage::Channels) -> bool {
+    let escaped = channels.flags & storage::Channels::ESCAPED != 0;
+    !escaped && (globals.colouring == 0 || globals.colouring == 6)
+}
+
+fn palette_colour(globals: &storage::ColouringGlobals, lut: &[Vec4], value: f32) -> Vec3 {
+    let t = (value * globals.palette_repeat + globals.palette_offset).rem_euclid(1.0);
+    let position = t * (lut.len() - 1) as f32;
+    let index = (position as usize).min(lut.len() - 1);
+    let a = lut[index];
+    let b = lut[(index + 1).min(lut.len() - 1)];
+    a.lerp(b, position.fract()).truncate()
+}
+
+fn colour(
+    globals: &storage::ColouringGlobals,
+    lut: &[Vec4],
+    cdf: &[f32],
+    channels: &storage::Channels,
+) -> Vec3 {
+    let colour = if is_interior(globals, channels) {
+        globals.interior
+    } else {
+        palette_colour(globals, lut, value(globals, cdf, channels))
+    };
+
+    if channels.flags & storage::Channels::FILLED != 0 && globals.show_subdivision != 0 {
+        colour.lerp(vec3(1.0, 0.0, 0.0), 0.5)
+    } else {
+        colour
+    }
+}
+
+fn gaussian_weight(offset: Vec2) -> f32 {
+    (-offset.dot(offset) / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA)).exp()
+}
+
+// Resolves the samples of every plane, and the extra samples of refined pixels, into the colour of
+// one pixel
+pub fn resolve(
+    globals: &storage::ColouringGlobals,
+    lut: &[Vec4],
+    cdf: &[f32],
+    frame: &Frame,
+    x: u32,
+    y: u32,
+) -> Vec3 {
+    let (width, height) = (frame.width as i32, frame.height as i32);
+    let sample_colour = |x: i32, y: i32, plane: usize| {
+        let index = (plane * frame.height as usize + y as usize) * frame.width as usize;
+        colour(globals, lut, cdf, &frame.channels[index + x as usize])
+    };
+
+    // Slot of a pixel plus one, where frames without refinement have no map at all
+    let slot = |x: i32, y: i32| {
+        let index = (y * width + x) as usize;
+        frame.refinement.get(index).copied().unwrap_or(0) as usize
+    };
+
+    let refined_samples = |x: i32, y: i32| match slot(x, y) {
+        0 => &[][..],
+        slot => &frame.refined[(slot - 1) * refinement::SAMPLES..slot * refinement::SAMPLES],
+    };
+
+    let (x, y) = (x as i32, y as i32);
+    let colour = if globals.reconstruction == Reconstruction::Box as u32 {
+        let refined = refined_samples(x, y);
+        let sum: Vec3 = (0..frame.samples.len())
+            .map(|plane| sample_colour(x, y, plane))
+            .chain(refined.iter().map(|c| colour(globals, lut, cdf, c)))
+            .sum();
+
+        sum / (frame.samples.len() + refined.len()) as f32
+    } else {
+        let mut sum = Vec3::ZERO;
+        let mut weights = 0.0;
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let (nx, ny) = (x + dx, y + dy);
+                if nx < 0 || ny < 0 || nx >= width || ny >= height {
+                    continue;
+                }
+
+                let corner = vec2(dx as f32, dy as f32) - 0.5;
+                for (plane, sample) in frame.samples.iter().enumerate() {
+                    let weight = gaussian_weight(corner + *sample);
+                    sum += weight * sample_colour(nx, ny, plane);
+                    weights += weight;
+                }
+
+                for (sample, channels) in refined_samples(nx, ny).iter().enumerate() {
+                    let weight = gaussian_weight(corner + refinement::offset(sample));
+                    sum += weight * colour(globals, lut, cdf, channels);
+                    weights += weight;
+                }
+            }
+        }
+
+        sum / weights
+    };
+
+    // Highlights the pixels that received extra samples
+    if globals.show_refinement != 0 && slot(x, y) != 0 {
+        colour.lerp(vec3(0.0, 1.0, 0.0), 0.5)
+    } else {
+        colour
+    }
+}
@@ -0,0 +1,226 @@
+use std::ops::{Add, Mul, Neg, Sub};
+
+use glam::f32::{vec2, Vec2};
+
+/*
+    Float-float arithmetic, where a number is the unevaluated sum of two f32 values and carries
+    about 48 bits of mantissa. Only needs plain f32 operations, so the shader can use it on any
+    GPU. Every function here mirrors its counterpart in compute.wgsl, and the two must be kept in
+    sync. The error-free transformations rely on every operation being rounded on its own, which
+    Rust guarantees and shader compilers are trusted not to break by reassociating.
+*/
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Df64 {
+    pub hi: f32,
+    pub lo: f32,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Complex {
+    pub re: Df64,
+    pub im: Df64,
+}
+
+// Splits an f32 into two halves of 12 bits each, whose products are exact
+const SPLITTER: f32 = 4097.0;
+
+// Exact sum of two values as the rounded sum and its error
+fn two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let v = s - a;
+    let e = (a - (s - v)) + (b - v);
+    Df64 { hi: s, lo: e }
+}
+
+// Like `two_sum`, for |a| >= |b|
+fn quick_two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let e = b - (s - a);
+    Df64 { hi: s, lo: e }
+}
+
+fn split(a: f32) -> (f32, f32) {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    (hi, a - hi)
+}
+
+// Exact product of two values as the rounded product and its error
+fn two_prod(a: f32, b: f32) -> Df64 {
+    let p = a * b;
+    let (a_hi, a_lo) = split(a);
+    let (b_hi, b_lo) = split(b);
+    let e = ((a_hi * b_hi - p) + a_hi * b_lo + a_lo * b_hi) + a_lo * b_lo;
+    Df64 { hi: p, lo: e }
+}
+
+impl Df64 {
+    pub fn new(hi: f32) -> Self {
+        Self { hi, lo: 0.0 }
+    }
+
+    #[cfg(test)]
+    pub fn from_f64(value: f64) -> Self {
+        let hi = value as f32;
+        Self {
+            hi,
+            lo: (value - hi as f64) as f32,
+        }
+    }
+
+    // Rounds an arbitrary precision value to the nearest float-float
+    pub fn from_float(value: &rug::Float) -> Self {
+        let hi = value.to_f32();
+        Self {
+            hi,
+            lo: (value.clone() - hi).to_f32(),
+        }
+    }
+
+    #[cfg(test)]
+    pub fn to_f64(self) -> f64 {
+        self.hi as f64 + self.lo as f64
+    }
+}
+
+impl Add for Df64 {
+    type Output = Df64;
+
+    fn add(self, other: Df64) -> Df64 {
+        let s = two_sum(self.hi, other.hi);
+        let t = two_sum(self.lo, other.lo);
+        let r = quick_two_sum(s.hi, s.lo + t.hi);
+        quick_two_sum(r.hi, r.lo + t.lo)
+    }
+}
+
+impl Neg for Df64 {
+    type Output = Df64;
+
+    fn neg(self) -> Df64 {
+        Df64 {
+            hi: -self.hi,
+            lo: -self.lo,
+        }
+    }
+}
+
+impl Sub for Df64 {
+    type Output = Df64;
+
+    fn sub(self, other: Df64) -> Df64 {
+        self + -other
+    }
+}
+
+impl Mul for Df64 {
+    type Output = Df64;
+
+    fn mul(self, other: Df64) -> Df64 {
+        let p = two_prod(self.hi, other.hi);
+        quick_two_sum(p.hi, p.lo + (self.hi * other.lo + self.lo * other.hi))
+    }
+}
+
+impl Complex {
+    pub fn new(re: Df64, im: Df64) -> Self {
+        Self { re, im }
+    }
+
+    // Leading parts only, for everything that is fine in single precision
+    pub fn hi(self) -> Vec2 {
+        vec2(self.re.hi, self.im.hi)
+    }
+}
+
+impl From<Vec2> for Complex {
+    fn from(value: Vec2) -> Self {
+        Self::new(Df64::new(value.x), Df64::new(value.y))
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nanorand::{Rng, WyRand};
+
+    // Float-float results carry about 44 bits after a few roundings
+    const TOLERANCE: f64 = 1e-13;
+
+    fn random(rng: &mut WyRand) -> f64 {
+        let mantissa = rng.generate::<f64>() * 2.0 - 1.0;
+        let exponent = rng.generate_range(0_u32..20) as i32 - 10;
+        mantissa * 2f64.powi(exponent)
+    }
+
+    fn assert_close(actual: Df64, expected: f64, scale: f64) {
+        let error = (actual.to_f64() - expected).abs();
+        assert!(
+            error <= TOLERANCE * scale,
+            "{} != {} (error {:e})",
+            actual.to_f64(),
+            expected,
+            error
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let mut rng = WyRand::new_seed(0xdf64);
+        for _ in 0..10_000 {
+            let (a, b) = (random(&mut rng), random(&mut rng));
+            let (x, y) = (Df64::from_f64(a), Df64::from_f64(b));
+            assert_close(x + y, a + b, a.abs().max(b.abs()));
+            assert_close(x - y, a - b, a.abs().max(b.abs()));
+            assert_close(x * y, a * b, (a * b).abs());
+        }
+
+        // Far beyond what a single f32 can resolve
+        let one = Df64::new(1.0);
+        let tiny = Df64::from_f64(1e-10);
+        assert_close(one + tiny - one, 1e-10, 1.0);
+        assert_ne!((one + tiny).lo, 0.0);
+    }
+
+    #[test]
+    fn test_complex() {
+        let mut rng = WyRand::new_seed(0xc0);
+        for _ in 0..1_000 {
+            let [a, b, c, d] = [(); 4].map(|_| random(&mut rng));
+            let x = Complex::new(Df64::from_f64(a), Df64::from_f64(b));
+            let y = Complex::new(Df64::from_f64(c), Df64::from_f64(d));
+            let product = x * y;
+            let scale = (a * c).abs() + (b * d).abs() + (a * d).abs() + (b * c).abs();
+            assert_close(product.re, a * c - b * d, scale);
+            assert_close(product.im, a * d + b * c, scale);
+        }
+    }
+}
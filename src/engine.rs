@@ -0,0 +1,295 @@
+pub mod cpu;
+pub mod gpu;
+
+use encase::ShaderSize;
+use glam::{f32::Vec2, f64::DVec2, i32::IVec2};
+
+use crate::{profiler::Profiler, refinement, storage, subdivision::Rect};
+
+/*
+    Engines turn a view description into raw per-pixel channels, which a separate colouring pass
+    turns into colours. The window and the headless renderer only talk to the `Engine` trait, so
+    any implementation can be swapped in at runtime.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EngineKind {
+    Gpu,
+    Cpu,
+}
+
+// Everything an engine needs to render one frame
+#[derive(Clone)]
+pub struct View {
+    pub width: u32,
+    pub height: u32,
+    pub globals: storage::Globals,
+    // Orbits of every reference one after another, all of the same length
+    pub orbit: Vec<Vec2>,
+    // The same orbits in f64 for the native kernel, empty when the view does not use it
+    pub orbit_f64: Vec<DVec2>,
+    // The scale and reference offsets in f64 for the native kernel, packed as its uniform by
+    // `reference::native_uniform` and empty when the view does not use it
+    pub native_globals: Vec<f64>,
+    // Where every reference starts, and the reference of every tile, see reference.rs
+    pub references: Vec<storage::Reference>,
+    pub reference_map: Vec<u32>,
+    pub traps: Vec<storage::Trap>,
+    pub subdivide: bool,
+    // Sub-pixel offsets of the samples, each rendered into its own plane of channels
+    pub samples: Vec<Vec2>,
+    // Whether high variance pixels are rendered again with extra samples once the frame is done
+    pub refine: bool,
+    // Only the GPU engine renders progressively, others always deliver complete frames
+    pub progressive: bool,
+    // Whole pixels the image moved by since the previous view, when only its center changed. New
+    // pixels take the channels of the pixel this far before them, and only the strips left
+    // uncovered need rendering.
+    pub shift: Option<IVec2>,
+}
+
+// Rendered data in memory, stored row by row from the bottom of the image like the texture, with
+// one full frame of channels for every sample
+#[derive(Default)]
+pub struct Frame {
+    pub width: u32,
+    pub height: u32,
+    pub channels: Vec<storage::Channels>,
+    pub samples: Vec<Vec2>,
+    // Slot of every pixel plus one, empty when nothing was refined, and the refined samples of
+    // every slot, see refinement.rs
+    pub refinement: Vec<u32>,
+    pub refined: Vec<storage::Channels>,
+}
+
+// Everything an engine renders into on the GPU, laid out like a `Frame`
+pub struct Buffers {
+    pub channels: wgpu::Buffer,
+    pub refinement: wgpu::Buffer,
+    pub refined: wgpu::Buffer,
+}
+
+pub enum Target<'a> {
+    // Buffers with copy destination usage, written by the recorded commands. Work on the GPU is
+    // timed by the profiler.
+    Buffer {
+        buffers: &'a Buffers,
+        queue: &'a wgpu::Queue,
+        encoder: &'a mut wgpu::CommandEncoder,
+        profiler: &'a Profiler,
+    },
+    Memory(&'a mut Frame),
+}
+
+pub trait Engine {
+    fn kind(&self) -> EngineKind;
+
+    fn render(&mut self, view: &View, target: Target);
+
+    // Fraction of the last requested view that has been rendered
+    fn progress(&self) -> f32;
+}
+
+impl View {
+    // Whether both views produce the same image, ignoring the animation time
+    pub fn same_image(&self, other: &View) -> bool {
+        let globals = storage::Globals {
+            time: other.globals.time,
+            ..self.globals
+        };
+
+        self.width == other.width
+            && self.height == other.height
+            && globals == other.globals
+            && self.orbit == other.orbit
+            && self.native_globals == other.native_globals
+            && self.references == other.references
+            && self.reference_map == other.reference_map
+            && self.traps == other.traps
+            && self.subdivide == other.subdivide
+            && self.samples == other.samples
+            && self.refine == other.refine
+            && self.progressive == other.progressive
+    }
+
+    // Whether this view shows another one moved by its shift, with everything else unchanged.
+    // References move along with the center, and kept pixels keep the ones they were rendered
+    // against.
+    pub fn is_shift_of(&self, other: &View) -> bool {
+        let Some(shift) = self.shift else {
+            return false;
+        };
+
+        let globals = storage::Globals {
+            time: other.globals.time,
+            center: other.globals.center,
+            ..self.globals
+        };
+
+        let size = IVec2::new(self.width as i32, self.height as i32);
+        shift.abs().cmplt(size).all()
+            && self.width == other.width
+            && self.height == other.height
+            && globals == other.globals
+            && self.orbit == other.orbit
+            && self.references.len() == other.references.len()
+            && self.traps == other.traps
+            && self.subdivide == other.subdivide
+            && self.samples == other.samples
+            && self.refine == other.refine
+    }
+
+    // Length of the orbit of every reference
+    pub fn iterations(&self) -> usize {
+        self.orbit.len() / self.references.len().max(1)
+    }
+
+    // Subdivision needs every tile border at full resolution, so it is never progressive
+    pub fn pass_count(&self) -> u32 {
+        if self.progressive && !self.subdivide {
+            gpu::PROGRESSIVE_PASSES
+        } else {
+            1
+        }
+    }
+}
+
+// Pixels of a frame left uncovered when its contents move by a shift, as a column and a row strip
+pub fn exposed(width: u32, height: u32, shift: IVec2) -> Vec<Rect> {
+    let (width, height) = (width as i32, height as i32);
+    let columns = if shift.x >= 0 {
+        (0, shift.x)
+    } else {
+        (width + shift.x, width)
+    };
+
+    let rows = if shift.y >= 0 {
+        (0, shift.y)
+    } else {
+        (height + shift.y, height)
+    };
+
+    // The row strip leaves out the corner already covered by the column strip
+    let covered = if shift.x >= 0 {
+        (shift.x, width)
+    } else {
+        (0, width + shift.x)
+    };
+
+    [
+        (columns.0, 0, columns.1 - columns.0, height),
+        (covered.0, rows.0, covered.1 - covered.0, rows.1 - rows.0),
+    ]
+    .into_iter()
+    .filter(|&(_, _, width, height)| width > 0 && height > 0)
+    .map(|(x, y, width, height)| Rect::new(x as u32, y as u32, width as u32, height as u32))
+    .collect()
+}
+
+// Moves every plane of channels by a shift, leaving the exposed pixels untouched
+pub fn shift_channels(
+    source: &[storage::Channels],
+    destination: &mut [storage::Channels],
+    width: u32,
+    height: u32,
+    shift: IVec2,
+) {
+    for (source, destination) in source
+        .chunks_exact((width * height) as usize)
+        .zip(destination.chunks_exact_mut((width * height) as usize))
+    {
+        for (y, x, length) in shifted_rows(width, height, shift) {
+            let from = ((y as i32 - shift.y) as u32 * width + (x as i32 - shift.x) as u32) as usize;
+            let to = (y * width + x) as usize;
+            destination[to..to + length as usize]
+                .copy_from_slice(&source[from..from + length as usize]);
+        }
+    }
+}
+
+// Destination row, first column and length of every run of pixels that a shift keeps in view
+pub fn shifted_rows(
+    width: u32,
+    height: u32,
+    shift: IVec2,
+) -> impl Iterator<Item = (u32, u32, u32)> {
+    let length = width - shift.x.unsigned_abs();
+    let x = shift.x.max(0) as u32;
+    (0..height)
+        .filter(move |&y| (0..height as i32).contains(&(y as i32 - shift.y)))
+        .map(move |y| (y, x, length))
+}
+
+impl Buffers {
+    pub fn new(
+        device: &wgpu::Device,
+        width: u32,
+        height: u32,
+        planes: usize,
+        refine: bool,
+        usage: wgpu::BufferUsages,
+    ) -> Self {
+        let pixels = width as u64 * height as u64;
+        let slots = refinement::capacity(width, height, refine) as u64;
+        let buffer = |label, size| {
+            device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(label),
+                size,
+                usage: wgpu::BufferUsages::STORAGE | usage,
+                mapped_at_creation: false,
+            })
+        };
+
+        let channel_size = storage::Channels::SHADER_SIZE.get();
+        Self {
+            channels: buffer("Channel buffer", channel_size * pixels * planes as u64),
+            refinement: buffer("Refinement map buffer", 4 * pixels),
+            refined: buffer(
+                "Refined channel buffer",
+                channel_size * slots * refinement::SAMPLES as u64,
+            ),
+        }
+    }
+
+    // Whether the buffers fit a frame of the given layout
+    pub fn matches(&self, width: u32, height: u32, planes: usize, refine: bool) -> bool {
+        let pixels = width as u64 * height as u64;
+        let slots = refinement::capacity(width, height, refine) as u64;
+        let channel_size = storage::Channels::SHADER_SIZE.get();
+        self.channels.size() == channel_size * pixels * planes as u64
+            && self.refinement.size() == 4 * pixels
+            && self.refined.size() == channel_size * slots * refinement::SAMPLES as u64
+    }
+}
+
+impl EngineKind {
+    pub const ALL: [EngineKind; 2] = [EngineKind::Gpu, EngineKind::Cpu];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EngineKind::Gpu => "GPU",
+            EngineKind::Cpu => "CPU",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exposed() {
+        let (width, height) = (10, 6);
+        for shift in [IVec2::new(3, -2), IVec2::new(-4, 1), IVec2::new(0, 5)] {
+            let rects = exposed(width, height, shift);
+            let area: u32 = rects.iter().map(|r| r.width * r.height).sum();
+            let kept: u32 = shifted_rows(width, height, shift).map(|(_, _, l)| l).sum();
+            assert_eq!(area + kept, width * height);
+        }
+
+        assert_eq!(
+            exposed(width, height, IVec2::new(3, -2)),
+            [Rect::new(0, 0, 3, 6), Rect::new(3, 4, 7, 2)]
+        );
+    }
+}
@@ -0,0 +1,1047 @@
+use std::{
+    f32::consts::PI,
+    ops::Range,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+    thread::JoinHandle,
+};
+
+use glam::f32::{vec2, Vec2};
+
+use super::{Engine, EngineKind, Frame, Target, View};
+use crate::{
+    df64::{self, Df64},
+    engine,
+    exponential::{self, Projection},
+    precision::Precision,
+    reference, refinement,
+    storage::{self, Storable},
+    subdivision,
+};
+
+/*
+    A multi-threaded implementation of the perturbation kernel in compute.wgsl. Every function
+    here mirrors its counterpart in the shader, and the two must be kept in sync.
+*/
+
+// Must be kept in sync with `GLITCH_TOLERANCE` in compute.wgsl
+const GLITCH_TOLERANCE: f32 = 1e-3;
+
+pub struct CpuEngine {
+    workers: usize,
+    // Set once the frame being rendered is no longer wanted, which stops it early
+    cancelled: Arc<AtomicBool>,
+    // Rows rendered so far
+    rows: Arc<AtomicUsize>,
+    job: Option<Job>,
+    // The view and channels of the last frame handed out, reused when the next view shifts it
+    last: Option<(View, Vec<storage::Channels>)>,
+}
+
+// A view rendering on a background thread, cancelled when it is dropped
+struct Job {
+    view: View,
+    cancelled: Arc<AtomicBool>,
+    rows: Arc<AtomicUsize>,
+    // Rows the frame takes, over every plane
+    total: usize,
+    // Taken once the frame has been handed out
+    handle: Option<JoinHandle<Frame>>,
+}
+
+pub struct Kernel<'a> {
+    pub globals: &'a storage::Globals,
+    // Orbits of every reference one after another, see `View::orbit`
+    pub orbit: &'a [Vec2],
+    pub references: &'a [storage::Reference],
+    pub reference_map: &'a [u32],
+    pub traps: &'a [storage::Trap],
+    // Position of the sample within every pixel, in pixels from its corner
+    pub offset: Vec2,
+}
+
+/*
+    Kernel
+*/
+
+fn cxmul(a: Vec2, b: Vec2) -> Vec2 {
+    vec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x)
+}
+
+fn cxdiv(a: Vec2, b: Vec2) -> Vec2 {
+    cxmul(a, vec2(b.x, -b.y)) / b.dot(b)
+}
+
+fn cxpow(a: Vec2, b: f32) -> Vec2 {
+    let r = a.length();
+    let theta = a.y.atan2(a.x);
+    let rprime = r.powf(b);
+    let thetaprime = theta * b;
+    vec2(rprime * thetaprime.cos(), rprime * thetaprime.sin())
+}
+
+fn trap_distance(trap: &storage::Trap, z: Vec2) -> f32 {
+    let offset = z - trap.position;
+    let along = offset.dot(trap.direction).abs();
+    let across = (offset.x * trap.direction.y - offset.y * trap.direction.x).abs();
+    match trap.kind {
+        1 => across,
+        2 => across.min(along),
+        3 => (offset.length() - trap.radius).abs(),
+        _ => offset.length(),
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+struct Average {
+    sum: f32,
+    last: f32,
+    count: f32,
+}
+
+impl Average {
+    fn push(self, t: f32) -> Self {
+        Self {
+            sum: self.sum + t,
+            last: t,
+            count: self.count + 1.0,
+        }
+    }
+
+    fn smooth(self, fraction: f32) -> f32 {
+        if self.count < 2.0 {
+            return self.sum;
+        }
+
+        let current = self.sum / self.count;
+        let previous = (self.sum - self.last) / (self.count - 1.0);
+        previous + (current - previous) * fraction
+    }
+}
+
+impl<'a> Kernel<'a> {
+    pub fn new(view: &'a View) -> Self {
+        Self {
+            globals: &view.globals,
+            orbit: &view.orbit,
+            references: &view.references,
+            reference_map: &view.reference_map,
+            traps: &view.traps,
+            offset: Vec2::ZERO,
+        }
+    }
+
+    // Length of the orbit of every reference
+    fn iterations(&self) -> usize {
+        self.orbit.len() / self.references.len()
+    }
+
+    pub fn mandelbrot(&self, d0_df: df64::Complex, reference: usize) -> storage::Channels {
+        let globals = self.globals;
+        let orbit = &self.orbit[reference * self.iterations()..][..self.iterations()];
+        let [a, b, c, d] = globals.coefficients.map(|c| c.truncate().truncate());
+        let emulated = globals.delta_precision == Precision::Emulated as u32;
+
+        let d0 = d0_df.hi();
+        let higher_terms =
+            cxmul(b, cxpow(d0, 2.0)) + cxmul(c, cxpow(d0, 3.0)) + cxmul(d, cxpow(d0, 4.0));
+        let mut dn = cxmul(a, d0) + higher_terms;
+        let mut dn_df = df64::Complex::from(a) * d0_df + higher_terms.into();
+        let mut trap_distance_min = 1e20;
+        let mut trap_iteration = 0;
+
+        let c_abs = (globals.center + self.references[reference].offset + d0).length();
+        let mut z = Vec2::ZERO;
+        let mut z_prev = Vec2::ZERO;
+        let mut z_prev2 = Vec2::ZERO;
+        let mut triangle_inequality = Average::default();
+        let mut stripe = Average::default();
+        let mut curvature = Average::default();
+        let mut escaped = false;
+        let mut glitched = false;
+        let mut derivative = Vec2::X;
+        let mut multiplier = Vec2::X;
+
+        let mut i = 0;
+        while i < orbit.len() {
+            let xn = orbit[i];
+
+            z = xn + dn;
+            for trap in self.traps {
+                let distance = trap_distance(trap, z);
+                if distance < trap_distance_min {
+                    trap_distance_min = distance;
+                    trap_iteration = i;
+                }
+            }
+
+            stripe = stripe.push(0.5 * (globals.stripe_density * z.y.atan2(z.x)).sin() + 0.5);
+
+            if i > 0 {
+                let z_prev_sqr = z_prev.dot(z_prev);
+                let lower = (z_prev_sqr - c_abs).abs();
+                let upper = z_prev_sqr + c_abs;
+                if upper - lower > 0.0 {
+                    triangle_inequality =
+                        triangle_inequality.push((z.length() - lower) / (upper - lower));
+                }
+            }
+
+            if i > 1 && z_prev != z_prev2 {
+                let turn = cxdiv(z - z_prev, z_prev - z_prev2);
+                curvature = curvature.push(turn.y.atan2(turn.x).abs() / PI);
+            }
+
+            if z.length() > globals.radius {
+                escaped = true;
+                break;
+            }
+
+            // Past its escape the reference repeats its last point, which no sample can follow
+            if xn.length() > globals.radius {
+                glitched = true;
+                break;
+            }
+
+            glitched |= z.length() < GLITCH_TOLERANCE * xn.length();
+
+            z_prev2 = z_prev;
+            z_prev = z;
+            derivative = 2.0 * cxmul(z, derivative) + Vec2::X;
+            multiplier = 2.0 * cxmul(z, multiplier);
+            if emulated {
+                dn_df = (df64::Complex::from(2.0 * xn) + dn_df) * dn_df + d0_df;
+                dn = dn_df.hi();
+            } else {
+                dn = cxmul(2.0 * xn + dn, dn) + d0;
+            }
+            i += 1;
+        }
+
+        let mut flags = if escaped {
+            storage::Channels::ESCAPED
+        } else if multiplier.length() < 1.0 {
+            storage::Channels::INTERIOR
+        } else {
+            0
+        };
+
+        if glitched {
+            flags |= storage::Channels::GLITCHED;
+        }
+
+        let iterations = orbit.len() as f32;
+        let mut escape = i as f32;
+        let mut fraction = 1.0;
+        let mut distance = 0.0;
+        if escaped {
+            let overshoot = (z.length().ln() / globals.radius.ln()).log2();
+            fraction = (1.0 - overshoot).clamp(0.0, 1.0);
+            escape += fraction;
+            distance = z.length() * z.length().ln() / derivative.length();
+        }
+
+        storage::Channels {
+            iterations: escape / iterations,
+            trap_distance: trap_distance_min,
+            trap_iteration: trap_iteration as f32 / iterations,
+            triangle_inequality: triangle_inequality.smooth(fraction),
+            stripe: stripe.smooth(fraction),
+            curvature: curvature.smooth(fraction),
+            final_z: z,
+            distance,
+            flags,
+        }
+    }
+
+    // Evaluates a position in pixels from the corner of the frame
+    pub fn sample_at(&self, position: Vec2, width: u32, height: u32) -> storage::Channels {
+        let offset = if self.globals.projection == Projection::Exponential as u32 {
+            exponential::offset(position, width, height)
+        } else {
+            let aspect_ratio = vec2(width as f32 / height as f32, 1.0);
+            let uv = position / vec2(width as f32, height as f32);
+            aspect_ratio * (uv - 0.5)
+        };
+
+        let reference = self.reference_map[reference::tile(position, width, height)] as usize;
+        let start = &self.references[reference];
+        let scale = Df64 {
+            hi: self.globals.scale,
+            lo: self.globals.scale_lo,
+        };
+
+        let orbit_offset = df64::Complex::new(
+            Df64 {
+                hi: start.offset.x,
+                lo: start.offset_lo.x,
+            },
+            Df64 {
+                hi: start.offset.y,
+                lo: start.offset_lo.y,
+            },
+        );
+
+        let x = df64::Complex::new(scale * Df64::new(offset.x), scale * Df64::new(offset.y));
+        self.mandelbrot(x - orbit_offset, reference)
+    }
+
+    pub fn sample(&self, x: u32, y: u32, width: u32, height: u32) -> storage::Channels {
+        self.sample_at(vec2(x as f32, y as f32) + self.offset, width, height)
+    }
+
+    fn escape_key(&self, channels: &storage::Channels) -> u32 {
+        (channels.iterations * self.iterations() as f32) as u32
+    }
+}
+
+/*
+    Engine
+*/
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl CpuEngine {
+    pub fn new() -> Self {
+        Self {
+            workers: std::thread::available_parallelism().map_or(1, |n| n.get()),
+            cancelled: Arc::default(),
+            rows: Arc::default(),
+            job: None,
+            last: None,
+        }
+    }
+
+    // Starts rendering a view in the background. A copy of the engine with a flag and a counter
+    // of its own renders it, so that it can be cancelled and followed.
+    fn start(&mut self, view: &View) {
+        // A view is shifted from the one before it, so the last frame is stale once a view was
+        // replaced before it was handed out
+        if self.job.as_ref().is_some_and(|job| job.handle.is_some()) {
+            self.last = None;
+        }
+
+        let last = self
+            .last
+            .take()
+            .filter(|(last, _)| view.is_shift_of(last) && !view.same_image(last))
+            .map(|(_, channels)| channels);
+
+        let total = match (view.shift, &last) {
+            (Some(shift), Some(_)) => exposed_runs(view.width, view.height, shift).len(),
+            _ => view.height as usize,
+        } * view.samples.len();
+
+        let (cancelled, rows) = (Arc::default(), Arc::default());
+        let engine = CpuEngine {
+            workers: self.workers,
+            cancelled: Arc::clone(&cancelled),
+            rows: Arc::clone(&rows),
+            job: None,
+            last: None,
+        };
+
+        let handle = {
+            let view = view.clone();
+            std::thread::spawn(move || engine.render_view(&view, last))
+        };
+
+        self.job = Some(Job {
+            view: view.clone(),
+            cancelled,
+            rows,
+            total,
+            handle: Some(handle),
+        });
+    }
+
+    // The frame of the job once it is done, handed out only once
+    fn finish(&mut self, wait: bool) -> Option<Frame> {
+        let job = self.job.as_mut()?;
+        if !wait && !job.handle.as_ref()?.is_finished() {
+            return None;
+        }
+
+        let handle = job.handle.take()?;
+        let frame = handle.join().expect("CPU render thread panicked");
+        self.last = Some((job.view.clone(), frame.channels.clone()));
+        Some(frame)
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    // A shifted view starts from the channels of the last frame and only renders the strips it
+    // exposes, otherwise every sample renders a full frame of its own
+    fn render_view(&self, view: &View, last: Option<Vec<storage::Channels>>) -> Frame {
+        let mut frame = Frame {
+            width: view.width,
+            height: view.height,
+            channels: Vec::new(),
+            samples: view.samples.clone(),
+            refinement: Vec::new(),
+            refined: Vec::new(),
+        };
+
+        if let (Some(shift), Some(channels)) = (view.shift, last) {
+            let (width, height) = (view.width, view.height);
+            frame.channels = vec![storage::Channels::default(); channels.len()];
+            engine::shift_channels(&channels, &mut frame.channels, width, height, shift);
+
+            let runs = exposed_runs(width, height, shift);
+            for (plane, &offset) in view.samples.iter().enumerate() {
+                let kernel = Kernel {
+                    offset,
+                    ..Kernel::new(view)
+                };
+
+                let start = plane * (width * height) as usize;
+                let rendered = self.render_runs(&kernel, width, height, &runs);
+                for ((y, columns), run) in runs.iter().zip(rendered) {
+                    let index = start + (y * width + columns.start) as usize;
+                    frame.channels[index..index + run.len()].copy_from_slice(&run);
+                }
+            }
+        } else {
+            for &offset in &view.samples {
+                let kernel = Kernel {
+                    offset,
+                    ..Kernel::new(view)
+                };
+
+                let plane = self.render_frame(&kernel, view.width, view.height, view.subdivide);
+                frame.channels.extend(plane.channels);
+            }
+        }
+
+        // A cancelled frame is thrown away, so its pixels are not worth refining
+        if view.refine && !self.cancelled() {
+            let (width, height) = (view.width, view.height);
+            let pixels = refinement::detect(
+                &frame.channels[..(width * height) as usize],
+                width,
+                height,
+                view.iterations(),
+                view.globals.refine_threshold,
+            );
+
+            let kernel = Kernel::new(view);
+            frame.refined = self.render_refined(&kernel, &pixels, width, height);
+            frame.refinement = refinement::slot_map(&pixels, width, height);
+        }
+
+        frame
+    }
+
+    // Renders a frame on the calling thread, which stops early and leaves it incomplete once the
+    // engine is cancelled
+    pub fn render_frame(&self, kernel: &Kernel, width: u32, height: u32, subdivide: bool) -> Frame {
+        let (mut channels, filled) = if subdivide {
+            let subdivision = subdivision::render(
+                width,
+                height,
+                |x, y| {
+                    if self.cancelled() {
+                        storage::Channels::default()
+                    } else {
+                        kernel.sample(x, y, width, height)
+                    }
+                },
+                |channels| kernel.escape_key(channels),
+            );
+
+            self.rows.fetch_add(height as usize, Ordering::Relaxed);
+            (subdivision.values, subdivision.filled)
+        } else {
+            (self.render_rows(kernel, width, height), Vec::new())
+        };
+
+        for rect in filled {
+            for y in rect.y + 1..rect.y + rect.height - 1 {
+                for x in rect.x + 1..rect.x + rect.width - 1 {
+                    channels[(y * width + x) as usize].flags |= storage::Channels::FILLED;
+                }
+            }
+        }
+
+        Frame {
+            width,
+            height,
+            channels,
+            samples: vec![kernel.offset],
+            refinement: Vec::new(),
+            refined: Vec::new(),
+        }
+    }
+
+    // Renders the extra samples of every detected pixel, split evenly between the workers
+    fn render_refined(
+        &self,
+        kernel: &Kernel,
+        pixels: &[u32],
+        width: u32,
+        height: u32,
+    ) -> Vec<storage::Channels> {
+        let chunk_size = pixels.len().div_ceil(self.workers).max(1);
+        std::thread::scope(|scope| {
+            let handles: Vec<_> = pixels
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    scope.spawn(move || {
+                        chunk
+                            .iter()
+                            .flat_map(|&index| {
+                                let pixel = vec2((index % width) as f32, (index / width) as f32);
+                                (0..refinement::SAMPLES).map(move |sample| {
+                                    let position = pixel + refinement::offset(sample);
+                                    kernel.sample_at(position, width, height)
+                                })
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("CPU refinement worker panicked"))
+                .collect()
+        })
+    }
+
+    fn render_rows(&self, kernel: &Kernel, width: u32, height: u32) -> Vec<storage::Channels> {
+        let runs: Vec<_> = (0..height).map(|y| (y, 0..width)).collect();
+        self.render_runs(kernel, width, height, &runs).concat()
+    }
+
+    // Renders runs of pixels, each a row and a range of columns in it, handed to the workers one
+    // at a time. Once cancelled only the runs up to some point are returned.
+    fn render_runs(
+        &self,
+        kernel: &Kernel,
+        width: u32,
+        height: u32,
+        runs: &[(u32, Range<u32>)],
+    ) -> Vec<Vec<storage::Channels>> {
+        let next_run = AtomicUsize::new(0);
+        let mut rendered: Vec<(usize, Vec<storage::Channels>)> = std::thread::scope(|scope| {
+            let handles: Vec<_> = (0..self.workers)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut rendered = Vec::new();
+                        loop {
+                            // Checked before taking a run, so every run taken gets rendered
+                            if self.cancelled() {
+                                break rendered;
+                            }
+
+                            let index = next_run.fetch_add(1, Ordering::Relaxed);
+                            let Some((y, columns)) = runs.get(index) else {
+                                break rendered;
+                            };
+
+                            let run = columns
+                                .clone()
+                                .map(|x| kernel.sample(x, *y, width, height))
+                                .collect();
+
+                            rendered.push((index, run));
+                            self.rows.fetch_add(1, Ordering::Relaxed);
+                        }
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|handle| handle.join().expect("CPU render worker panicked"))
+                .collect()
+        });
+
+        rendered.sort_by_key(|(index, _)| *index);
+        rendered.into_iter().map(|(_, run)| run).collect()
+    }
+}
+
+// The rows of the strips a shift exposes, as runs of pixels
+fn exposed_runs(width: u32, height: u32, shift: glam::IVec2) -> Vec<(u32, Range<u32>)> {
+    engine::exposed(width, height, shift)
+        .into_iter()
+        .flat_map(|rect| {
+            (rect.y..rect.y + rect.height).map(move |y| (y, rect.x..rect.x + rect.width))
+        })
+        .collect()
+}
+
+impl Engine for CpuEngine {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Cpu
+    }
+
+    // Views render in the background, the buffers keep holding the last frame until the next one
+    // is done. Rendering into memory waits for the frame instead.
+    fn render(&mut self, view: &View, target: Target) {
+        // Rendering into memory needs a frame to hand out, so a view already handed out renders
+        // again
+        let wait = matches!(target, Target::Memory(_));
+        let current = self.job.as_ref().filter(|job| job.view.same_image(view));
+        if !current.is_some_and(|job| job.handle.is_some() || !wait) {
+            self.start(view);
+        }
+
+        let Some(frame) = self.finish(wait) else {
+            return;
+        };
+
+        match target {
+            Target::Buffer { buffers, queue, .. } => {
+                let bytes = storage::Array(&frame.channels).to_bytes();
+                queue.write_buffer(&buffers.channels, 0, &bytes);
+
+                let pixels = (view.width * view.height) as usize;
+                let refinement = if frame.refinement.is_empty() {
+                    vec![0; pixels]
+                } else {
+                    frame.refinement
+                };
+
+                queue.write_buffer(&buffers.refinement, 0, bytemuck::cast_slice(&refinement));
+                if !frame.refined.is_empty() {
+                    let bytes = storage::Array(&frame.refined).to_bytes();
+                    queue.write_buffer(&buffers.refined, 0, &bytes);
+                }
+            }
+            Target::Memory(output) => *output = frame,
+        }
+    }
+
+    // Counts the rows of every plane until the frame is handed out, which takes the last step
+    fn progress(&self) -> f32 {
+        match &self.job {
+            Some(job) if job.handle.is_some() => {
+                let rows = job.rows.load(Ordering::Relaxed);
+                rows as f32 / (job.total + 1) as f32
+            }
+            Some(_) => 1.0,
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mandelbrot;
+    use glam::f32::Vec4;
+
+    const PRECISION: u32 = 64;
+    const ITERATIONS: usize = 200;
+
+    // A single reference at the center of the view, used by every tile
+    const REFERENCES: [storage::Reference; 1] = [storage::Reference {
+        offset: Vec2::ZERO,
+        offset_lo: Vec2::ZERO,
+    }];
+    const REFERENCE_MAP: [u32; reference::TILE_COUNT] = [0; reference::TILE_COUNT];
+
+    fn globals(center: (f64, f64), scale: f32) -> storage::Globals {
+        storage::Globals {
+            time: 0.0,
+            scale,
+            radius: 2.0,
+            center: vec2(center.0 as f32, center.1 as f32),
+            stripe_density: 5.0,
+            refine_threshold: refinement::DEFAULT_THRESHOLD,
+            projection: Projection::Flat as u32,
+            delta_precision: Precision::Single as u32,
+            scale_lo: 0.0,
+            coefficients: [Vec4::X, Vec4::ZERO, Vec4::ZERO, Vec4::ZERO],
+        }
+    }
+
+    fn orbit(center: (f64, f64)) -> Vec<Vec2> {
+        let z = rug::Complex::with_val(PRECISION, (0.0, 0.0));
+        let c = rug::Complex::with_val(PRECISION, center);
+        let r = rug::Float::with_val(PRECISION, 2.0);
+        mandelbrot::compute_reference_orbit(z, c, r, ITERATIONS, false).points
+    }
+
+    // A view around the main cardioid, tests only override the fields they exercise
+    fn view(width: u32, height: u32) -> View {
+        let center = (-0.5, 0.0);
+        View {
+            width,
+            height,
+            globals: globals(center, 3.0),
+            orbit: orbit(center),
+            orbit_f64: Vec::new(),
+            native_globals: Vec::new(),
+            references: REFERENCES.to_vec(),
+            reference_map: REFERENCE_MAP.to_vec(),
+            traps: Vec::new(),
+            subdivide: false,
+            samples: vec![Vec2::ZERO],
+            refine: false,
+            progressive: false,
+            shift: None,
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        let center = (-0.5, 0.0);
+        let (globals, orbit) = (globals(center, 3.0), orbit(center));
+        let kernel = Kernel {
+            globals: &globals,
+            orbit: &orbit,
+            references: &REFERENCES,
+            reference_map: &REFERENCE_MAP,
+            traps: &[],
+            offset: Vec2::ZERO,
+        };
+
+        let frame = CpuEngine::new().render_frame(&kernel, 32, 24, false);
+        assert_eq!(frame.channels.len(), 32 * 24);
+
+        // The center is inside the main cardioid, while the corners escape almost immediately
+        assert_eq!(frame.channels[12 * 32 + 16].iterations, 1.0);
+        assert!(frame.channels[0].iterations < 0.05);
+    }
+
+    #[test]
+    fn test_subdivision_matches_direct_render() {
+        let center = (-0.12, 0.75);
+        let (globals, orbit) = (globals(center, 0.5), orbit(center));
+        let kernel = Kernel {
+            globals: &globals,
+            orbit: &orbit,
+            references: &REFERENCES,
+            reference_map: &REFERENCE_MAP,
+            traps: &[],
+            offset: Vec2::ZERO,
+        };
+
+        let engine = CpuEngine::new();
+        let direct = engine.render_frame(&kernel, 64, 48, false);
+        let subdivided = engine.render_frame(&kernel, 64, 48, true);
+        let matching = direct
+            .channels
+            .iter()
+            .zip(subdivided.channels.iter())
+            .filter(|(a, b)| kernel.escape_key(a) == kernel.escape_key(b))
+            .count();
+
+        assert!(matching as f32 > 0.99 * direct.channels.len() as f32);
+    }
+
+    #[test]
+    fn test_engine_renders_into_memory() {
+        let view = View {
+            samples: crate::sampling::jittered(2),
+            ..view(16, 8)
+        };
+
+        let mut engine: Box<dyn Engine> = Box::new(CpuEngine::new());
+        let mut frame = Frame::default();
+        engine.render(&view, Target::Memory(&mut frame));
+
+        assert_eq!(engine.progress(), 1.0);
+        assert_eq!((frame.width, frame.height), (16, 8));
+        assert_eq!(frame.samples.len(), 4);
+        assert_eq!(frame.channels.len(), 16 * 8 * 4);
+
+        // Every plane samples its own sub-pixel position
+        let plane = 16 * 8;
+        assert_ne!(frame.channels[..plane], frame.channels[plane..2 * plane]);
+    }
+
+    #[test]
+    fn test_shift_matches_direct_render() {
+        let center = (-0.12, 0.75);
+        let view = View {
+            globals: globals(center, 0.5),
+            orbit: orbit(center),
+            ..view(48, 32)
+        };
+
+        // Moving the center right and down by whole pixels moves the image left and up
+        let pixel_size = view.globals.scale / view.height as f32;
+        let moved = vec2(5.0, -3.0) * pixel_size;
+        let shifted = View {
+            globals: storage::Globals {
+                center: view.globals.center + moved,
+                ..view.globals
+            },
+            references: vec![storage::Reference {
+                offset: -moved,
+                offset_lo: Vec2::ZERO,
+            }],
+            shift: Some(glam::ivec2(-5, 3)),
+            ..view.clone()
+        };
+
+        let mut engine = CpuEngine::new();
+        let mut frame = Frame::default();
+        engine.render(&view, Target::Memory(&mut frame));
+        engine.render(&shifted, Target::Memory(&mut frame));
+
+        let mut direct = Frame::default();
+        let unshifted = View {
+            shift: None,
+            ..shifted.clone()
+        };
+
+        CpuEngine::new().render(&unshifted, Target::Memory(&mut direct));
+
+        // Reused pixels sit a rounding error away from where a direct render samples them
+        let kernel = Kernel::new(&view);
+        let matching = direct
+            .channels
+            .iter()
+            .zip(frame.channels.iter())
+            .filter(|(a, b)| kernel.escape_key(a) == kernel.escape_key(b))
+            .count();
+
+        assert!(matching as f32 > 0.99 * direct.channels.len() as f32);
+        assert_eq!(frame.channels[47], direct.channels[47]);
+    }
+
+    #[test]
+    fn test_references_match_single_reference() {
+        // The center and every reference of the grid stay bounded around this view
+        let center = (-0.8, 0.1);
+        let (width, height, scale) = (48, 32, 0.2);
+        let single = View {
+            globals: globals(center, scale),
+            orbit: orbit(center),
+            ..view(width, height)
+        };
+
+        // A grid of four references, each taking the quadrant around it
+        let offsets = reference::grid(2, reference::extent(width, height, Projection::Flat));
+        let starts: Vec<(f64, f64)> = offsets
+            .iter()
+            .map(|o| {
+                (
+                    center.0 + (o.x * scale) as f64,
+                    center.1 + (o.y * scale) as f64,
+                )
+            })
+            .collect();
+
+        let grid = View {
+            orbit: starts.iter().flat_map(|&start| orbit(start)).collect(),
+            references: starts
+                .iter()
+                .map(|start| storage::Reference {
+                    offset: vec2((start.0 - center.0) as f32, (start.1 - center.1) as f32),
+                    offset_lo: Vec2::ZERO,
+                })
+                .collect(),
+            reference_map: reference::assign(width, height, Projection::Flat, &offsets),
+            ..single.clone()
+        };
+
+        let (mut expected, mut frame) = (Frame::default(), Frame::default());
+        CpuEngine::new().render(&single, Target::Memory(&mut expected));
+        CpuEngine::new().render(&grid, Target::Memory(&mut frame));
+
+        let kernel = Kernel::new(&single);
+        let matching = expected
+            .channels
+            .iter()
+            .zip(frame.channels.iter())
+            .filter(|(a, b)| kernel.escape_key(a) == kernel.escape_key(b))
+            .count();
+
+        assert!(matching as f32 > 0.99 * expected.channels.len() as f32);
+        assert_eq!(grid.iterations(), ITERATIONS);
+    }
+
+    // Perturbation in f64 against the same f32 orbit, following the loop of `Kernel::mandelbrot`
+    fn escape_f64(orbit: &[Vec2], d0: glam::DVec2) -> (usize, glam::DVec2) {
+        let mul = |a: glam::DVec2, b: glam::DVec2| {
+            glam::dvec2(a.x * b.x - a.y * b.y, a.x * b.y + a.y * b.x)
+        };
+
+        let mut dn = d0;
+        let mut z = glam::DVec2::ZERO;
+        for (i, xn) in orbit.iter().map(|x| x.as_dvec2()).enumerate() {
+            z = xn + dn;
+            if z.length() > 2.0 {
+                return (i, z);
+            }
+
+            dn = mul(2.0 * xn + dn, dn) + d0;
+        }
+
+        (orbit.len(), z)
+    }
+
+    #[test]
+    fn test_emulated_precision() {
+        let center = (-0.743643887037151, 0.131825904205330);
+        let orbit = orbit(center);
+        let (width, height, scale) = (48, 32, 2e-5);
+
+        // Pixels whose escape iteration and final value agree with the f64 perturbation
+        let matching = |precision: Precision| {
+            let globals = storage::Globals {
+                delta_precision: precision as u32,
+                ..globals(center, scale as f32)
+            };
+
+            let kernel = Kernel {
+                globals: &globals,
+                orbit: &orbit,
+                references: &REFERENCES,
+                reference_map: &REFERENCE_MAP,
+                traps: &[],
+                offset: Vec2::ZERO,
+            };
+
+            // The same offsets as the kernel, whose products with the scale are exact in both
+            let aspect_ratio = vec2(width as f32 / height as f32, 1.0);
+            (0..width * height)
+                .filter(|i| {
+                    let pixel = vec2((i % width) as f32, (i / width) as f32);
+                    let channels = kernel.sample_at(pixel, width, height);
+
+                    let uv = pixel / vec2(width as f32, height as f32);
+                    let d0 = (scale as f32) as f64 * (aspect_ratio * (uv - 0.5)).as_dvec2();
+                    let (escape, z) = escape_f64(&orbit, d0);
+
+                    let iterations = (channels.iterations * orbit.len() as f32) as usize;
+                    iterations == escape && (channels.final_z.as_dvec2() - z).length() < 1e-4
+                })
+                .count()
+        };
+
+        let (single, emulated) = (matching(Precision::Single), matching(Precision::Emulated));
+        assert!(emulated as f32 > 0.99 * (width * height) as f32);
+
+        // Single precision deltas visibly drift at this depth
+        assert!(single < emulated);
+    }
+
+    #[test]
+    fn test_refinement() {
+        let center = (-0.75, 0.1);
+        let view = View {
+            globals: globals(center, 0.2),
+            orbit: orbit(center),
+            refine: true,
+            ..view(32, 24)
+        };
+
+        let mut frame = Frame::default();
+        CpuEngine::new().render(&view, Target::Memory(&mut frame));
+
+        // The view straddles the boundary, so some but not all pixels are refined
+        let slots = frame.refinement.iter().filter(|&&slot| slot != 0).count();
+        assert_eq!(frame.refinement.len(), 32 * 24);
+        assert!(slots > 0 && slots < 32 * 24);
+        assert_eq!(frame.refined.len(), slots * refinement::SAMPLES);
+
+        // The samples of a slot belong to the pixel that points at it
+        let index = frame.refinement.iter().position(|&slot| slot != 0).unwrap();
+        let slot = frame.refinement[index] as usize - 1;
+        let kernel = Kernel::new(&view);
+        let pixel = vec2((index % 32) as f32, (index / 32) as f32);
+        let expected = kernel.sample_at(pixel + refinement::offset(0), 32, 24);
+        assert_eq!(frame.refined[slot * refinement::SAMPLES], expected);
+    }
+
+    #[test]
+    fn test_distance_estimate() {
+        let center = (-0.5, 0.0);
+        let (globals, orbit) = (globals(center, 3.0), orbit(center));
+        let kernel = Kernel {
+            globals: &globals,
+            orbit: &orbit,
+            references: &REFERENCES,
+            reference_map: &REFERENCE_MAP,
+            traps: &[],
+            offset: Vec2::ZERO,
+        };
+
+        // c = 1 is exactly 0.75 away from the cardioid cusp at 0.25
+        let outside = kernel.mandelbrot(vec2(1.5, 0.0).into(), 0);
+        assert_eq!(outside.flags, storage::Channels::ESCAPED);
+        assert!(outside.distance > 0.1 && outside.distance < 0.75);
+
+        let inside = kernel.mandelbrot(Vec2::ZERO.into(), 0);
+        assert_eq!(inside.flags, storage::Channels::INTERIOR);
+        assert_eq!(inside.distance, 0.0);
+
+        // The orbit of c = 0 stays at zero while the reference does not, which perturbation cannot
+        // resolve
+        let glitched = kernel.mandelbrot(vec2(0.5, 0.0).into(), 0);
+        assert_eq!(
+            glitched.flags,
+            storage::Channels::INTERIOR | storage::Channels::GLITCHED
+        );
+    }
+
+    #[test]
+    fn test_escaped_reference() {
+        // The reference at c = 1 escapes after a few iterations, c = -0.5 never does
+        let center = (1.0, 0.0);
+        let (globals, orbit) = (globals(center, 3.0), orbit(center));
+        let kernel = Kernel {
+            globals: &globals,
+            orbit: &orbit,
+            references: &REFERENCES,
+            reference_map: &REFERENCE_MAP,
+            traps: &[],
+            offset: Vec2::ZERO,
+        };
+
+        let outside = kernel.mandelbrot(vec2(1.0, 0.0).into(), 0);
+        assert_eq!(outside.flags, storage::Channels::ESCAPED);
+
+        let inside = kernel.mandelbrot(vec2(-1.5, 0.0).into(), 0);
+        assert_ne!(inside.flags & storage::Channels::GLITCHED, 0);
+        assert_eq!(inside.flags & storage::Channels::ESCAPED, 0);
+    }
+
+    #[test]
+    fn test_engine_renders_in_background() {
+        let view = view(32, 24);
+        let mut engine = CpuEngine::new();
+        engine.start(&view);
+        assert!(engine.progress() < 1.0);
+
+        let frame = engine
+            .finish(true)
+            .expect("The job has a frame to hand out");
+        assert_eq!(engine.progress(), 1.0);
+        assert!(engine.finish(true).is_none());
+
+        let expected = engine.render_view(&view, None);
+        assert_eq!(frame.channels, expected.channels);
+    }
+
+    #[test]
+    fn test_trap_distance() {
+        let z = vec2(3.0, 4.0);
+        let trap = |kind| storage::Trap {
+            kind,
+            radius: 1.0,
+            position: Vec2::ZERO,
+            direction: Vec2::X,
+        };
+
+        assert_eq!(trap_distance(&trap(0), z), 5.0);
+        assert_eq!(trap_distance(&trap(1), z), 4.0);
+        assert_eq!(trap_distance(&trap(2), z), 3.0);
+        assert_eq!(trap_distance(&trap(3), z), 4.0);
+    }
+}
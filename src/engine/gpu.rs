@@ -0,0 +1,815 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+
+use encase::{ShaderSize, ShaderType};
+use glam::i32::IVec2;
+use wgpu::util::DeviceExt;
+
+use super::{Buffers, Engine, EngineKind, Frame, Target, View};
+use crate::{
+    engine, pipeline,
+    profiler::{self, Profiler},
+    reference, refinement,
+    storage::{self, Storable},
+    subdivision::{self, Rect},
+    trap,
+};
+
+// Must be kept in sync with the workgroup sizes in compute.wgsl
+const WORKGROUP_SIZE: u32 = 8;
+
+// Pixels covered by a single dispatch along each axis. Frames are split into tiles of this size
+// and submitted separately to keep every submission well below GPU watchdog timeouts.
+const DISPATCH_TILE_SIZE: u32 = 256;
+
+// Passes of progressive refinement, the first one evaluating every 8th pixel along each axis
+pub const PROGRESSIVE_PASSES: u32 = 4;
+
+// Refined samples rendered by a single dispatch, as many as a dispatch tile has pixels
+const REFINE_DISPATCH_SIZE: u32 = DISPATCH_TILE_SIZE * DISPATCH_TILE_SIZE;
+
+// Time spent rendering tiles every frame before handing control back to the window
+const FRAME_BUDGET: Duration = Duration::from_millis(12);
+
+// Tiles submitted ahead of the GPU. The window never waits on a tile, it stops submitting for the
+// frame once this many are still running, so slow tiles cannot hold up the interface.
+const TILES_IN_FLIGHT: usize = 4;
+
+pub struct GpuEngine {
+    device: Arc<wgpu::Device>,
+    queue: Arc<wgpu::Queue>,
+    pipeline: pipeline::compute::ComputePipeline,
+    resources: Option<Resources>,
+    job: Option<Job>,
+}
+
+// Recreated whenever the size of the view or the length of its reference orbit changes
+struct Resources {
+    width: u32,
+    height: u32,
+    orbit_length: usize,
+    sample_count: usize,
+    refine: bool,
+    globals_buffer: wgpu::Buffer,
+    orbit_buffer: wgpu::Buffer,
+    // Only written for views that use the native kernel
+    orbit_f64_buffer: wgpu::Buffer,
+    native_buffer: wgpu::Buffer,
+    reference_buffer: wgpu::Buffer,
+    trap_buffer: wgpu::Buffer,
+    sample_buffer: wgpu::Buffer,
+    // Pixels found by the detection dispatches, behind an atomic counter
+    refine_list_buffer: wgpu::Buffer,
+    // Back buffers, copied to the target when a job starts and whenever a pass of it completes
+    buffers: Buffers,
+    tile_stride: u32,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Stage {
+    Render,
+    // Finds the pixels to refine once every pass is done
+    Detect,
+    // Renders the extra samples of a range of refined pixels
+    Refine,
+}
+
+// One tile of one sample plane in one progressive pass, or a range of refined samples
+struct Dispatch {
+    stage: Stage,
+    rect: Rect,
+    pass_index: u32,
+    plane: u32,
+}
+
+// A view being rendered tile by tile over several frames
+struct Job {
+    view: View,
+    subdivide: bool,
+    dispatches: Vec<Dispatch>,
+    pass_count: u32,
+    bind_group: wgpu::BindGroup,
+    next_dispatch: usize,
+    // Dispatches the GPU has finished, counted as their submissions complete
+    finished: Arc<AtomicUsize>,
+    // Dispatches submitted before the last copy to the target
+    shown: usize,
+    presented: bool,
+}
+
+impl Resources {
+    fn new(device: &wgpu::Device, view: &View) -> Self {
+        let globals_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Globals buffer"),
+            contents: &storage::Uniform(&view.globals).to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let orbit_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Reference orbits buffer"),
+            contents: &storage::Buffer(&view.orbit).to_bytes(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let orbit_f64_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Reference orbits f64 buffer"),
+            size: 16 * view.orbit.len().max(1) as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let native_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Native globals buffer"),
+            size: reference::NATIVE_UNIFORM_SIZE,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let reference_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("References buffer"),
+            contents: &storage::Uniform(&reference::uniform(&view.references, &view.reference_map))
+                .to_bytes(),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let trap_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Orbit traps buffer"),
+            contents: {
+                let traps = [storage::Trap::default(); trap::MAX_TRAPS];
+                let buffer = storage::Buffer(&traps);
+                &buffer.to_bytes()
+            },
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let sample_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Sample offsets buffer"),
+            contents: &storage::Array(&view.samples).to_bytes(),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let buffers = Buffers::new(
+            device,
+            view.width,
+            view.height,
+            view.samples.len(),
+            view.refine,
+            wgpu::BufferUsages::COPY_SRC | wgpu::BufferUsages::COPY_DST,
+        );
+
+        let capacity = refinement::capacity(view.width, view.height, view.refine) as u64;
+        let refine_list_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Refinement list buffer"),
+            size: 4 + 4 * capacity,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            width: view.width,
+            height: view.height,
+            orbit_length: view.orbit.len(),
+            sample_count: view.samples.len(),
+            refine: view.refine,
+            globals_buffer,
+            orbit_buffer,
+            orbit_f64_buffer,
+            native_buffer,
+            reference_buffer,
+            trap_buffer,
+            sample_buffer,
+            refine_list_buffer,
+            buffers,
+            tile_stride: device.limits().min_uniform_buffer_offset_alignment,
+        }
+    }
+
+    fn matches(&self, view: &View) -> bool {
+        self.width == view.width
+            && self.height == view.height
+            && self.orbit_length == view.orbit.len()
+            && self.sample_count == view.samples.len()
+            && self.refine == view.refine
+    }
+}
+
+impl Job {
+    // Lays out the dispatches of a view. A shifted view only renders the exposed strips, at full
+    // resolution right away since they are narrow.
+    fn new(
+        device: &wgpu::Device,
+        pipeline: &pipeline::compute::ComputePipeline,
+        resources: &Resources,
+        view: &View,
+        shift: Option<IVec2>,
+    ) -> Self {
+        let tiles = dispatch_tiles(Rect::new(0, 0, view.width, view.height));
+        let (pass_count, rects) = match shift {
+            Some(shift) => (1, engine::exposed(view.width, view.height, shift)),
+            None => (
+                view.pass_count(),
+                vec![Rect::new(0, 0, view.width, view.height)],
+            ),
+        };
+
+        // Every pass covers the whole frame in every plane before the next one starts
+        let planes = view.samples.len() as u32;
+        let render_tiles: Vec<Rect> = rects.into_iter().flat_map(dispatch_tiles).collect();
+        let mut dispatches: Vec<Dispatch> = (0..pass_count)
+            .flat_map(|pass_index| (0..planes).map(move |plane| (pass_index, plane)))
+            .flat_map(|(pass_index, plane)| {
+                render_tiles.iter().map(move |rect| Dispatch {
+                    stage: Stage::Render,
+                    rect: *rect,
+                    pass_index,
+                    plane,
+                })
+            })
+            .collect();
+
+        // Refinement covers every slot, and dispatches past the detected pixels return early
+        if view.refine {
+            let detect = tiles.iter().map(|rect| Dispatch {
+                stage: Stage::Detect,
+                rect: *rect,
+                pass_index: 0,
+                plane: 0,
+            });
+
+            let capacity = refinement::capacity(view.width, view.height, true) as u32;
+            let samples = capacity * refinement::SAMPLES as u32;
+            let refine = (0..samples)
+                .step_by(REFINE_DISPATCH_SIZE as usize)
+                .map(|first| Dispatch {
+                    stage: Stage::Refine,
+                    rect: Rect::new(first, 0, REFINE_DISPATCH_SIZE.min(samples - first), 1),
+                    pass_index: 0,
+                    plane: 0,
+                });
+
+            dispatches.extend(detect.chain(refine));
+        }
+
+        // Every tile origin lives at its own dynamic offset
+        let tile_stride = resources.tile_stride;
+        let tile_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Tile buffer"),
+            contents: &{
+                let mut bytes = vec![0; dispatches.len().max(1) * tile_stride as usize];
+                for (i, dispatch) in dispatches.iter().enumerate() {
+                    let tile = storage::Tile {
+                        origin: glam::uvec2(dispatch.rect.x, dispatch.rect.y),
+                        dimensions: glam::uvec2(view.width, view.height),
+                        pass_index: dispatch.pass_index,
+                        pass_count,
+                        plane: dispatch.plane,
+                    };
+
+                    let tile_bytes = storage::Uniform(&tile).to_bytes();
+                    let offset = i * tile_stride as usize;
+                    bytes[offset..offset + tile_bytes.len()].copy_from_slice(&tile_bytes);
+                }
+
+                bytes
+            },
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("Compute bind group"),
+            layout: &pipeline.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: resources.globals_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: resources.orbit_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: resources.trap_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: resources.buffers.channels.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &tile_buffer,
+                        offset: 0,
+                        size: Some(storage::Tile::min_size()),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: resources.sample_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: resources.refine_list_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: resources.buffers.refinement.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: resources.buffers.refined.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: resources.orbit_f64_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: resources.reference_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 11,
+                    resource: resources.native_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        Self {
+            view: view.clone(),
+            subdivide: view.subdivide && shift.is_none(),
+            dispatches,
+            pass_count,
+            bind_group,
+            next_dispatch: 0,
+            finished: Arc::new(AtomicUsize::new(0)),
+            shown: 0,
+            presented: false,
+        }
+    }
+
+    fn is_complete(&self) -> bool {
+        self.next_dispatch >= self.dispatches.len()
+    }
+
+    fn in_flight(&self) -> usize {
+        self.next_dispatch - self.finished.load(Ordering::Acquire)
+    }
+}
+
+// Whether a pass over every plane, or the whole job, has been submitted since the target was last
+// copied to. Refinement only counts once it is done, as it is copied with everything else.
+fn pass_ended(dispatches: &[Dispatch], next: usize, shown: usize) -> bool {
+    let last = next.checked_sub(1).map(|i| &dispatches[i]);
+    match (last, dispatches.get(next)) {
+        (_, None) => true,
+        (Some(last), Some(next_dispatch)) => {
+            next > shown
+                && last.stage == Stage::Render
+                && (next_dispatch.stage != Stage::Render
+                    || next_dispatch.pass_index != last.pass_index)
+        }
+        (None, Some(_)) => false,
+    }
+}
+
+// Splits a rectangle of a frame into the tiles covered by each dispatch
+fn dispatch_tiles(rect: Rect) -> Vec<Rect> {
+    let mut tiles = Vec::new();
+    for y in (rect.y..rect.y + rect.height).step_by(DISPATCH_TILE_SIZE as usize) {
+        for x in (rect.x..rect.x + rect.width).step_by(DISPATCH_TILE_SIZE as usize) {
+            let tile_width = DISPATCH_TILE_SIZE.min(rect.x + rect.width - x);
+            let tile_height = DISPATCH_TILE_SIZE.min(rect.y + rect.height - y);
+            tiles.push(Rect::new(x, y, tile_width, tile_height));
+        }
+    }
+
+    tiles
+}
+
+fn copy_to_buffer(
+    device: &wgpu::Device,
+    encoder: &mut wgpu::CommandEncoder,
+    bytes: &[u8],
+    destination: &wgpu::Buffer,
+) {
+    let staging = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytes,
+        usage: wgpu::BufferUsages::COPY_SRC,
+    });
+
+    encoder.copy_buffer_to_buffer(
+        &staging,
+        0,
+        destination,
+        0,
+        bytes.len() as wgpu::BufferAddress,
+    );
+}
+
+// Maps a buffer for reading and blocks until the data is available
+fn read_buffer(device: &wgpu::Device, buffer: &wgpu::Buffer) -> Vec<u8> {
+    let slice = buffer.slice(..);
+    let (sender, receiver) = std::sync::mpsc::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        sender.send(result).ok();
+    });
+
+    device.poll(wgpu::Maintain::Wait);
+    receiver
+        .recv()
+        .expect("Readback callback was dropped")
+        .expect("Unable to map readback buffer");
+
+    let bytes = slice.get_mapped_range().to_vec();
+    buffer.unmap();
+    bytes
+}
+
+impl GpuEngine {
+    pub fn new(device: Arc<wgpu::Device>, queue: Arc<wgpu::Queue>) -> Self {
+        Self {
+            pipeline: pipeline::compute::ComputePipeline::new(&device),
+            device,
+            queue,
+            resources: None,
+            job: None,
+        }
+    }
+
+    // Uploads the view and starts rendering it from the first tile, with every pixel pending until
+    // then. When it shifts the last view, which has been presented in full, the front buffers are
+    // copied back at their new position.
+    fn start(&mut self, view: &View, front: Option<&Buffers>, profiler: Option<&Profiler>) {
+        let shift = view.shift.filter(|_| {
+            let last = self.job.as_ref().filter(|job| job.presented);
+            front.is_some() && last.is_some_and(|job| view.is_shift_of(&job.view))
+        });
+
+        if !self.resources.as_ref().is_some_and(|r| r.matches(view)) {
+            self.resources = Some(Resources::new(&self.device, view));
+        }
+
+        let device = &self.device;
+        let resources = self.resources.as_ref().unwrap();
+        let job = Job::new(device, &self.pipeline, resources, view, shift);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Upload encoder"),
+        });
+
+        if let Some(profiler) = profiler {
+            profiler.stamp(&mut encoder, profiler::Stage::Upload, false);
+        }
+
+        // Copy globals, orbits, references, traps and sample offsets to GPU
+        copy_to_buffer(
+            device,
+            &mut encoder,
+            &storage::Uniform(&view.globals).to_bytes(),
+            &resources.globals_buffer,
+        );
+
+        copy_to_buffer(
+            device,
+            &mut encoder,
+            &storage::Buffer(&view.orbit).to_bytes(),
+            &resources.orbit_buffer,
+        );
+
+        if !view.orbit_f64.is_empty() {
+            let orbit: Vec<f64> = view.orbit_f64.iter().flat_map(|x| x.to_array()).collect();
+            copy_to_buffer(
+                device,
+                &mut encoder,
+                bytemuck::cast_slice(&orbit),
+                &resources.orbit_f64_buffer,
+            );
+
+            copy_to_buffer(
+                device,
+                &mut encoder,
+                bytemuck::cast_slice(&view.native_globals),
+                &resources.native_buffer,
+            );
+        }
+
+        copy_to_buffer(
+            device,
+            &mut encoder,
+            &storage::Uniform(&reference::uniform(&view.references, &view.reference_map))
+                .to_bytes(),
+            &resources.reference_buffer,
+        );
+
+        copy_to_buffer(
+            device,
+            &mut encoder,
+            &storage::Buffer(&view.traps).to_bytes(),
+            &resources.trap_buffer,
+        );
+
+        copy_to_buffer(
+            device,
+            &mut encoder,
+            &storage::Array(&view.samples).to_bytes(),
+            &resources.sample_buffer,
+        );
+
+        // Nothing is refined until the detection dispatches have run
+        encoder.clear_buffer(&resources.refine_list_buffer, 0, None);
+        encoder.clear_buffer(&resources.buffers.refinement, 0, None);
+
+        // Every plane at once, using the frame size of the first tile
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Clear pass"),
+            });
+
+            compute_pass.set_pipeline(&self.pipeline.clear_pipeline);
+            compute_pass.set_bind_group(0, &job.bind_group, &[0]);
+            compute_pass.dispatch_workgroups(
+                view.width.div_ceil(WORKGROUP_SIZE),
+                view.height.div_ceil(WORKGROUP_SIZE),
+                view.samples.len() as u32,
+            );
+        }
+
+        // Rows are copied one by one, every plane moving by the same shift
+        if let (Some(shift), Some(front)) = (shift, front) {
+            let size = storage::Channels::SHADER_SIZE.get();
+            let width = view.width as u64;
+            let plane_size = width * view.height as u64 * size;
+            for plane in 0..view.samples.len() as u64 {
+                for (y, x, length) in engine::shifted_rows(view.width, view.height, shift) {
+                    let source_y = (y as i32 - shift.y) as u64;
+                    let source_x = (x as i32 - shift.x) as u64;
+                    encoder.copy_buffer_to_buffer(
+                        &front.channels,
+                        plane * plane_size + (source_y * width + source_x) * size,
+                        &resources.buffers.channels,
+                        plane * plane_size + (y as u64 * width + x as u64) * size,
+                        length as u64 * size,
+                    );
+                }
+            }
+        }
+
+        // The target shows the cleared back buffers, where the placeholder stands in for every
+        // pixel but the shifted ones, until the first pass is done
+        if let Some(front) = front {
+            encoder.copy_buffer_to_buffer(
+                &resources.buffers.channels,
+                0,
+                &front.channels,
+                0,
+                front.channels.size(),
+            );
+            encoder.clear_buffer(&front.refinement, 0, None);
+        }
+
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.job = Some(job);
+    }
+
+    // Submits one tile at a time until the job is complete or the deadline has passed. With a
+    // deadline, submitting also stops while too many tiles are in flight, as waiting on them would
+    // block the window for as long as the slowest tile takes.
+    fn advance(&mut self, deadline: Option<Instant>, profiler: Option<&Profiler>) {
+        let (Some(resources), Some(job)) = (&self.resources, &mut self.job) else {
+            return;
+        };
+
+        // Tiles are submitted one by one, so the stage is timed in submissions of its own
+        let timed = profiler.filter(|_| !job.is_complete());
+        if let Some(profiler) = timed {
+            profiler.stamp_now(&self.device, &self.queue, profiler::Stage::Compute, false);
+        }
+
+        self.device.poll(wgpu::Maintain::Poll);
+        while let Some(dispatch) = job.dispatches.get(job.next_dispatch) {
+            if deadline.is_some() && job.in_flight() >= TILES_IN_FLIGHT {
+                break;
+            }
+
+            let rect = dispatch.rect;
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Tile encoder"),
+                });
+
+            {
+                let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("Compute pass"),
+                });
+
+                let offset = job.next_dispatch as u32 * resources.tile_stride;
+                compute_pass.set_bind_group(0, &job.bind_group, &[offset]);
+                if dispatch.stage == Stage::Detect {
+                    compute_pass.set_pipeline(&self.pipeline.detect_pipeline);
+                    compute_pass.dispatch_workgroups(
+                        rect.width.div_ceil(WORKGROUP_SIZE),
+                        rect.height.div_ceil(WORKGROUP_SIZE),
+                        1,
+                    );
+                } else if dispatch.stage == Stage::Refine {
+                    // Workgroups are as large as the 2D ones, laid out in a line
+                    let size = WORKGROUP_SIZE * WORKGROUP_SIZE;
+                    compute_pass.set_pipeline(&self.pipeline.refine_pipeline);
+                    compute_pass.dispatch_workgroups(rect.width.div_ceil(size), 1, 1);
+                } else if job.subdivide {
+                    // Every invocation covers a whole subdivision tile
+                    let span = subdivision::TILE_SIZE * WORKGROUP_SIZE;
+                    compute_pass.set_pipeline(&self.pipeline.subdivide_pipeline);
+                    compute_pass.dispatch_workgroups(
+                        rect.width.div_ceil(span),
+                        rect.height.div_ceil(span),
+                        1,
+                    );
+                } else {
+                    // Every invocation covers a block on the grid of its pass
+                    let block = 1 << (job.pass_count - 1 - dispatch.pass_index);
+                    compute_pass.set_pipeline(&self.pipeline.pipeline);
+                    compute_pass.dispatch_workgroups(
+                        rect.width.div_ceil(block).div_ceil(WORKGROUP_SIZE),
+                        rect.height.div_ceil(block).div_ceil(WORKGROUP_SIZE),
+                        1,
+                    );
+                }
+            }
+
+            self.queue.submit(std::iter::once(encoder.finish()));
+            let finished = job.finished.clone();
+            self.queue.on_submitted_work_done(move || {
+                finished.fetch_add(1, Ordering::Release);
+            });
+
+            job.next_dispatch += 1;
+
+            if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                break;
+            }
+        }
+
+        if let Some(profiler) = timed {
+            profiler.stamp_now(&self.device, &self.queue, profiler::Stage::Compute, true);
+        }
+    }
+
+    // Copies a buffer into a mappable one and reads it back
+    fn read_back(&self, buffer: &wgpu::Buffer) -> Vec<u8> {
+        let readback = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback buffer"),
+            size: buffer.size(),
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback encoder"),
+            });
+
+        encoder.copy_buffer_to_buffer(buffer, 0, &readback, 0, buffer.size());
+        self.queue.submit(std::iter::once(encoder.finish()));
+        read_buffer(&self.device, &readback)
+    }
+
+    // Renders every tile of the view and reads the channels back
+    fn render_to_memory(&mut self, view: &View) -> Frame {
+        self.start(view, None, None);
+        self.advance(None, None);
+
+        let buffers = &self.resources.as_ref().unwrap().buffers;
+        let (refinement, refined) = if view.refine {
+            (
+                bytemuck::pod_collect_to_vec(&self.read_back(&buffers.refinement)),
+                storage::array_from_bytes(&self.read_back(&buffers.refined)),
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        Frame {
+            width: view.width,
+            height: view.height,
+            channels: storage::array_from_bytes(&self.read_back(&buffers.channels)),
+            samples: view.samples.clone(),
+            refinement,
+            refined,
+        }
+    }
+}
+
+impl Engine for GpuEngine {
+    fn kind(&self) -> EngineKind {
+        EngineKind::Gpu
+    }
+
+    fn render(&mut self, view: &View, target: Target) {
+        match target {
+            Target::Buffer {
+                buffers,
+                encoder,
+                profiler,
+                ..
+            } => {
+                if !self.job.as_ref().is_some_and(|j| j.view.same_image(view)) {
+                    self.start(view, Some(buffers), Some(profiler));
+                }
+
+                self.advance(Some(Instant::now() + FRAME_BUDGET), Some(profiler));
+
+                // The target only ever shows whole passes, never tiles of a pass still underway.
+                // Refined samples only show up once all of them are done.
+                let (Some(resources), Some(job)) = (&self.resources, &mut self.job) else {
+                    return;
+                };
+
+                let complete = job.is_complete();
+                if !job.presented && pass_ended(&job.dispatches, job.next_dispatch, job.shown) {
+                    let copy =
+                        |encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer, target| {
+                            encoder.copy_buffer_to_buffer(source, 0, target, 0, source.size());
+                        };
+
+                    copy(encoder, &resources.buffers.channels, &buffers.channels);
+                    if complete {
+                        copy(encoder, &resources.buffers.refinement, &buffers.refinement);
+                        copy(encoder, &resources.buffers.refined, &buffers.refined);
+                    }
+
+                    job.shown = job.next_dispatch;
+                    job.presented = complete;
+                }
+            }
+            Target::Memory(output) => *output = self.render_to_memory(view),
+        }
+    }
+
+    fn progress(&self) -> f32 {
+        match (&self.resources, &self.job) {
+            (Some(_), Some(job)) => job.next_dispatch as f32 / job.dispatches.len() as f32,
+            _ => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dispatch_tiles_cover_frame() {
+        let (width, height) = (600, 300);
+        let tiles = dispatch_tiles(Rect::new(0, 0, width, height));
+        assert_eq!(tiles.len(), 3 * 2);
+
+        let area: u32 = tiles.iter().map(|t| t.width * t.height).sum();
+        assert_eq!(area, width * height);
+
+        // Subdivision tiles must never straddle two dispatches
+        let span = subdivision::TILE_SIZE * WORKGROUP_SIZE;
+        assert_eq!(DISPATCH_TILE_SIZE % span, 0);
+        assert!(tiles.iter().all(|t| t.x % span == 0 && t.y % span == 0));
+
+        // Progressive grids line up with the tiles as well
+        let coarsest = 1 << (PROGRESSIVE_PASSES - 1);
+        assert_eq!(DISPATCH_TILE_SIZE % (coarsest * WORKGROUP_SIZE), 0);
+    }
+
+    #[test]
+    fn test_pass_ended() {
+        let dispatch = |stage, pass_index| Dispatch {
+            stage,
+            rect: Rect::new(0, 0, 1, 1),
+            pass_index,
+            plane: 0,
+        };
+
+        // Two tiles in each of two passes, then detection and refinement
+        let dispatches = [
+            dispatch(Stage::Render, 0),
+            dispatch(Stage::Render, 0),
+            dispatch(Stage::Render, 1),
+            dispatch(Stage::Render, 1),
+            dispatch(Stage::Detect, 0),
+            dispatch(Stage::Refine, 0),
+        ];
+
+        let ended: Vec<usize> = (0..=dispatches.len())
+            .filter(|next| pass_ended(&dispatches, *next, 0))
+            .collect();
+        assert_eq!(ended, [2, 4, 6]);
+
+        // A pass that has already been shown is not shown again
+        assert!(!pass_ended(&dispatches, 2, 2));
+        assert!(pass_ended(&[], 0, 0));
+    }
+}
@@ -0,0 +1,202 @@
+use std::f32::consts::{LN_2, TAU};
+
+use glam::f32::{vec2, Vec2, Vec4};
+
+use crate::headless::Image;
+
+/*
+    Exponential map rendering. Columns of a strip sweep the full circle around the center and rows
+    step down in log radius by the same angle, so every pixel is roughly square. The top row lies
+    at the radius of the view's scale, and every `width / TAU * ln 2` rows further down the radius
+    halves, so one strip holds a whole zoom sequence. Ordinary frames are made from it by mapping
+    every pixel back onto the strip, see `assemble`.
+*/
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Projection {
+    Flat,
+    Exponential,
+}
+
+impl Projection {
+    pub const ALL: [Projection; 2] = [Projection::Flat, Projection::Exponential];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Projection::Flat => "Flat",
+            Projection::Exponential => "Exponential map",
+        }
+    }
+}
+
+// Footprints of frame pixels larger than this many strip pixels are averaged over this many
+const MAX_FOOTPRINT: i32 = 8;
+
+// Rows a strip of the given width needs to cover a number of halvings of the radius
+pub fn strip_height(width: u32, octaves: f32) -> u32 {
+    (octaves * LN_2 * width as f32 / TAU).ceil().max(1.0) as u32
+}
+
+// Halvings of the radius covered by a strip
+pub fn octaves(width: u32, height: u32) -> f32 {
+    height as f32 * TAU / (width as f32 * LN_2)
+}
+
+// Offset from the center of the view at a position in strip pixels, in units of the view's scale.
+// Must be kept in sync with `exponential_offset` in compute.wgsl.
+pub fn offset(position: Vec2, width: u32, height: u32) -> Vec2 {
+    let angle = TAU * position.x / width as f32;
+    let radius = (TAU * (position.y - height as f32) / width as f32).exp();
+    radius * vec2(angle.cos(), angle.sin())
+}
+
+// Position in strip pixels of an offset from the center, the inverse of `offset`
+fn position(offset: Vec2, width: u32, height: u32) -> Vec2 {
+    let angle = offset.y.atan2(offset.x).rem_euclid(TAU);
+    let x = angle * width as f32 / TAU;
+    let y = height as f32 + offset.length().ln() * width as f32 / TAU;
+    vec2(x, y)
+}
+
+// Size of the largest frame that fits inside the outermost circle of a strip, relative to the
+// scale of the view it was rendered from
+pub fn frame_scale(width: u32, height: u32) -> f32 {
+    2.0 / vec2(width as f32 / height as f32, 1.0).length()
+}
+
+// Deepest zoom, in octaves below the outermost frame, at which the part of a frame missing from
+// the bottom of the strip stays below half a pixel. Negative when not even the outermost frame
+// can be assembled.
+pub fn max_depth(strip: &Image, width: u32, height: u32) -> f32 {
+    let innermost = (-TAU * strip.height as f32 / strip.width as f32).exp();
+    let half_pixel = 0.5 * frame_scale(width, height) / height as f32;
+    (half_pixel / innermost).log2()
+}
+
+// Renders a frame of a zoom sequence from a strip, `depth` octaves below the outermost frame. Strip
+// pixels are averaged over the footprint of every frame pixel, which grows towards the center.
+pub fn assemble(strip: &Image, width: u32, height: u32, depth: f32) -> Result<Image, String> {
+    let size = (strip.width as usize).checked_mul(strip.height as usize);
+    if size.filter(|&size| size > 0) != Some(strip.pixels.len()) {
+        return Err(format!(
+            "A {}x{} strip cannot hold {} pixels",
+            strip.width,
+            strip.height,
+            strip.pixels.len()
+        ));
+    }
+
+    let (strip_width, strip_height) = (strip.width as i32, strip.height as i32);
+
+    // Rows of the image are stored from the top, while strip positions count from the bottom
+    let load = |x: i32, y: i32| {
+        let x = x.rem_euclid(strip_width);
+        let y = y.clamp(0, strip_height - 1);
+        strip.pixels[((strip_height - 1 - y) * strip_width + x) as usize]
+    };
+
+    let bilinear = |position: Vec2| {
+        let base = position.floor();
+        let t = position - base;
+        let (x, y) = (base.x as i32, base.y as i32);
+        let bottom = load(x, y).lerp(load(x + 1, y), t.x);
+        let top = load(x, y + 1).lerp(load(x + 1, y + 1), t.x);
+        bottom.lerp(top, t.y)
+    };
+
+    let scale = frame_scale(width, height) * (-depth).exp2();
+    let pixel_size = scale / height as f32;
+    let aspect_ratio = vec2(width as f32 / height as f32, 1.0);
+
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| {
+            // Pixel centers, with the first row at the top
+            let uv =
+                vec2(x as f32 + 0.5, (height - y) as f32 - 0.5) / vec2(width as f32, height as f32);
+            let offset = scale * aspect_ratio * (uv - 0.5);
+            let center = position(offset, strip.width, strip.height) - 0.5;
+
+            // Strip pixels covered by one frame pixel along each axis, equal since both are square
+            let footprint =
+                pixel_size / offset.length().max(f32::MIN_POSITIVE) * strip.width as f32 / TAU;
+            let count = (footprint.round() as i32).clamp(1, MAX_FOOTPRINT);
+            if count == 1 {
+                return bilinear(center);
+            }
+
+            let origin = center - 0.5 * (count - 1) as f32;
+            let sum: Vec4 = (0..count)
+                .flat_map(|j| (0..count).map(move |i| vec2(i as f32, j as f32)))
+                .map(|step| bilinear(origin + step))
+                .sum();
+
+            sum / (count * count) as f32
+        })
+        .collect();
+
+    Ok(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offset_round_trip() {
+        let (width, height) = (64, strip_height(64, 4.0));
+        // Rounded up to whole rows
+        let covered = octaves(width, height);
+        assert!(covered >= 4.0 && covered - 4.0 < octaves(width, 1));
+
+        // The top row lies on the radius of the scale, and every octave further down halves it
+        let top = offset(vec2(0.0, height as f32), width, height);
+        assert!(top.abs_diff_eq(Vec2::X, 1e-6));
+
+        let rows = LN_2 * width as f32 / TAU;
+        let halved = offset(vec2(16.0, height as f32 - rows), width, height);
+        assert!(halved.abs_diff_eq(vec2(0.0, 0.5), 1e-5));
+
+        for position in [vec2(3.5, 10.25), vec2(40.0, 2.0), vec2(63.0, 30.0)] {
+            let back = super::position(offset(position, width, height), width, height);
+            assert!(back.abs_diff_eq(position, 1e-3));
+        }
+    }
+
+    #[test]
+    fn test_assemble() {
+        // Colours every strip pixel by its row, so frames are brighter towards their edges
+        let (width, height) = (64, 48);
+        let strip = Image {
+            width,
+            height,
+            pixels: (0..height)
+                .rev()
+                .flat_map(|y| (0..width).map(move |_| Vec4::splat(y as f32 / height as f32)))
+                .collect(),
+        };
+
+        let frame = assemble(&strip, 16, 16, 0.0).unwrap();
+        let corner = frame.pixels[0];
+        let middle = frame.pixels[8 * 16 + 8];
+        assert!(corner.x > middle.x);
+
+        // Zooming in by an octave moves every radius up by the rows of an octave
+        let deeper = assemble(&strip, 16, 16, 1.0).unwrap();
+        let rows = LN_2 * width as f32 / TAU;
+        let expected = corner.x - rows / height as f32;
+        assert!((deeper.pixels[0].x - expected).abs() < 0.02);
+
+        assert!(max_depth(&strip, 16, 16) > 1.0);
+
+        let short = Image {
+            height: height + 1,
+            ..strip
+        };
+        assert!(assemble(&short, 16, 16, 0.0).is_err());
+    }
+}
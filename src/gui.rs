@@ -1,19 +1,59 @@
+use std::collections::VecDeque;
+
 use rug::{ops::CompleteRound, Assign};
 
-use crate::{app, precision::PRECISION};
+use crate::{
+    app,
+    certified::{self, Classification},
+    colouring::Colouring,
+    engine::{Engine, EngineKind},
+    exponential::Projection,
+    palette::{Palette, Stop},
+    precision::{Precision, PRECISION},
+    profiler::{self, FrameTimes, Stage},
+    reference,
+    sampling::{self, Reconstruction},
+    trap::{self, Trap, TrapKind},
+};
 
 pub struct Input {
     pub mouse_drag: Option<glam::f32::Vec2>,
     pub mouse_scroll: Option<f32>,
+    pub place_trap: Option<(usize, glam::f32::Vec2)>,
 }
 
 pub struct Interface {
     info_pane: InfoPane,
+    rendering_pane: RenderingPane,
+    colouring_pane: ColouringPane,
+    trap_editor: TrapEditor,
+    certified_pane: CertifiedPane,
     position_toolbar: PositionToolbar,
 }
 
 pub struct InfoPane;
 
+pub struct RenderingPane;
+
+pub struct ColouringPane {
+    path: String,
+    status: String,
+}
+
+pub struct TrapEditor {
+    placing: Option<usize>,
+}
+
+pub struct CertifiedPane {
+    width: u32,
+    height: u32,
+    iterations: usize,
+    max_period: usize,
+    job: Option<std::thread::JoinHandle<certified::CertifiedMap>>,
+    map: Option<certified::CertifiedMap>,
+    status: String,
+}
+
 pub struct PositionToolbar {
     real: LargeFloatEditor,
     imag: LargeFloatEditor,
@@ -29,6 +69,10 @@ impl Interface {
     pub fn new() -> Self {
         Self {
             info_pane: InfoPane,
+            rendering_pane: RenderingPane,
+            colouring_pane: ColouringPane::new(),
+            trap_editor: TrapEditor { placing: None },
+            certified_pane: CertifiedPane::new(),
             position_toolbar: PositionToolbar {
                 real: LargeFloatEditor::new(),
                 imag: LargeFloatEditor::new(),
@@ -37,11 +81,16 @@ impl Interface {
         }
     }
 
-    pub fn ui(&mut self, ctx: &egui::Context, globals: &mut app::Globals) -> Input {
+    pub fn ui(
+        &mut self,
+        ctx: &egui::Context,
+        globals: &mut app::Globals,
+        engine: &dyn Engine,
+    ) -> Input {
         egui::CentralPanel::default()
             .frame(egui::Frame::none())
             .show(ctx, |ui| {
-                let input = ui.input(|i| Input {
+                let mut input = ui.input(|i| Input {
                     mouse_drag: {
                         if i.pointer.is_decidedly_dragging() {
                             let (x, y) = i.pointer.delta().into();
@@ -58,18 +107,58 @@ impl Interface {
                             None
                         }
                     },
+                    place_trap: None,
                 });
 
+                let clicked = ui
+                    .interact(ui.max_rect(), ui.id().with("plane"), egui::Sense::click())
+                    .interact_pointer_pos()
+                    .filter(|_| ui.input(|i| i.pointer.primary_released()));
+
                 egui::Window::new("Info")
                     .default_open(true)
                     .show(ctx, |ui: &mut egui::Ui| {
                         self.info_pane.ui(ui, globals);
                     });
 
+                egui::Window::new("Rendering").default_open(false).show(
+                    ctx,
+                    |ui: &mut egui::Ui| {
+                        self.rendering_pane.ui(ui, globals, engine);
+                    },
+                );
+
+                egui::Window::new("Colouring").default_open(false).show(
+                    ctx,
+                    |ui: &mut egui::Ui| {
+                        self.colouring_pane.ui(ui, globals);
+                    },
+                );
+
+                egui::Window::new("Orbit traps").default_open(false).show(
+                    ctx,
+                    |ui: &mut egui::Ui| {
+                        self.trap_editor.ui(ui, globals);
+                    },
+                );
+
+                egui::Window::new("Certified render")
+                    .default_open(false)
+                    .show(ctx, |ui: &mut egui::Ui| {
+                        self.certified_pane.ui(ui, globals);
+                    });
+
                 egui::panel::TopBottomPanel::bottom("bottom").show(ctx, |ui| {
                     self.position_toolbar.ui(ui, globals);
                 });
 
+                if let (Some(index), Some(pos)) = (self.trap_editor.placing, clicked) {
+                    let screen = ctx.screen_rect();
+                    let uv = (pos - screen.min) / screen.size();
+                    input.place_trap = Some((index, glam::f32::vec2(uv.x, uv.y)));
+                    self.trap_editor.placing = None;
+                }
+
                 input
             })
             .inner
@@ -87,6 +176,630 @@ impl InfoPane {
             ui.label("Time");
             ui.label(egui::RichText::new(format!("{:.2}", globals.timing.time)).monospace());
         });
+
+        draw_section(ui, "Device", |ui| {
+            ui.label("Numeric path").on_hover_text(
+                "Arithmetic of the perturbation step in the active engine, which falls back to \
+                 emulation when native double precision is unavailable",
+            );
+            ui.label(globals.numeric_path().name());
+        });
+
+        draw_section(ui, "Frame time", |ui| {
+            let frames = &globals.timing.frames;
+            ui.label("GPU").on_hover_text(
+                "Time spent in every pass on the GPU for the last measured frames, stacked",
+            );
+            if globals.timestamps {
+                draw_frame_times(ui, frames);
+            } else {
+                ui.label("Timestamps unsupported");
+            }
+
+            ui.end_row();
+
+            // Averages over the history, skipping frames in which a stage did not run
+            for stage in Stage::ALL.into_iter().filter(|_| globals.timestamps) {
+                let times: Vec<f32> = frames
+                    .iter()
+                    .filter_map(|frame| frame.stages[stage as usize])
+                    .collect();
+                let average = times.iter().sum::<f32>() / times.len().max(1) as f32;
+                ui.label(egui::RichText::new(stage.name()).color(stage_colour(stage)));
+                ui.label(egui::RichText::new(format!("{average:.3} ms")).monospace());
+
+                ui.end_row();
+            }
+
+            ui.label("Orbit (CPU)").on_hover_text(
+                "Time spent computing the reference orbits when the view last changed",
+            );
+            let orbit = frames.iter().rev().find_map(|frame| frame.orbit);
+            let text = orbit.map_or("-".to_string(), |orbit| format!("{orbit:.3} ms"));
+            ui.label(egui::RichText::new(text).monospace());
+        });
+
+        draw_section(ui, "Statistics", |ui| {
+            let statistics = &globals.statistics;
+            let total = statistics.samples().max(1) as f32;
+            let counts = [
+                (
+                    "Escaped",
+                    statistics.escaped,
+                    "Samples whose orbit left the escape radius",
+                ),
+                (
+                    "Interior",
+                    statistics.interior,
+                    "Samples whose orbit is attracted to a cycle",
+                ),
+                (
+                    "Max iteration",
+                    statistics.max_iteration,
+                    "Samples that reached the iteration limit without settling into a cycle",
+                ),
+                (
+                    "Glitched",
+                    statistics.glitched,
+                    "Samples that lost precision against their reference orbit",
+                ),
+            ];
+
+            for (name, count, hover) in counts {
+                ui.label(name).on_hover_text(hover);
+                let percentage = 100.0 * count as f32 / total;
+                ui.label(egui::RichText::new(format!("{count} ({percentage:.1}%)")).monospace());
+
+                ui.end_row();
+            }
+
+            ui.label("Escape range")
+                .on_hover_text("First and last iteration at which a sample escaped");
+            let range = statistics
+                .escape_range
+                .map_or("-".to_string(), |(min, max)| format!("{min} - {max}"));
+            ui.label(egui::RichText::new(range).monospace());
+
+            ui.end_row();
+
+            ui.label("Skipped").on_hover_text(
+                "Iterations saved per sample by filling in subdivided tiles instead of evaluating them",
+            );
+            ui.label(egui::RichText::new(format!("{:.2}", statistics.skipped)).monospace());
+        });
+    }
+}
+
+impl RenderingPane {
+    fn ui(&mut self, ui: &mut egui::Ui, globals: &mut app::Globals, engine: &dyn Engine) {
+        draw_section(ui, "Engine", |ui| {
+            ui.label("Engine");
+            egui::ComboBox::from_id_source("engine")
+                .selected_text(globals.engine.name())
+                .show_ui(ui, |ui| {
+                    for kind in EngineKind::ALL {
+                        ui.selectable_value(&mut globals.engine, kind, kind.name());
+                    }
+                });
+
+            ui.end_row();
+
+            ui.label("Precision").on_hover_text(
+                "Arithmetic of the perturbation step, where double precision is slower but stays \
+                 clean at deeper zooms. Native double precision needs a device with f64 support.",
+            );
+            egui::ComboBox::from_id_source("precision")
+                .selected_text(globals.precision.name())
+                .show_ui(ui, |ui| {
+                    for precision in Precision::ALL {
+                        if precision != Precision::Native || globals.shader_f64 {
+                            ui.selectable_value(
+                                &mut globals.precision,
+                                precision,
+                                precision.name(),
+                            );
+                        }
+                    }
+                });
+
+            ui.end_row();
+
+            ui.label("References").on_hover_text(
+                "Reference orbits along each side of the view, every part of which perturbs \
+                 against the closest one",
+            );
+            ui.add(egui::Slider::new(
+                &mut globals.reference_grid,
+                1..=reference::MAX_GRID,
+            ));
+
+            ui.end_row();
+
+            ui.label("Iterations").on_hover_text(
+                "Length of the reference orbits. The automatic mode raises it when too many \
+                 samples reach it and lowers it when every sample escapes well before, and editing \
+                 the value takes over manually.",
+            );
+            ui.horizontal(|ui| {
+                let limit = egui::DragValue::new(&mut globals.iterations)
+                    .clamp_range(app::MIN_ITERATIONS..=app::MAX_ITERATIONS)
+                    .speed(10.0);
+                if ui.add(limit).changed() {
+                    globals.auto_iterations = false;
+                }
+
+                ui.checkbox(&mut globals.auto_iterations, "Auto");
+            });
+
+            ui.end_row();
+
+            ui.label("Progress");
+            ui.add(egui::ProgressBar::new(engine.progress()).show_percentage());
+        });
+
+        draw_section(ui, "Resolution", |ui| {
+            ui.label("Render scale");
+            ui.add(
+                egui::Slider::new(
+                    &mut globals.render_scale,
+                    app::MIN_RENDER_SCALE..=app::MAX_RENDER_SCALE,
+                )
+                .logarithmic(true)
+                .suffix("x"),
+            );
+
+            ui.end_row();
+
+            ui.label("Progressive")
+                .on_hover_text("Show a coarse image first and refine it over several passes");
+            ui.checkbox(&mut globals.progressive, "");
+
+            ui.end_row();
+
+            ui.label("Dynamic")
+                .on_hover_text("Lower the resolution while dragging or zooming");
+            ui.checkbox(&mut globals.dynamic_resolution, "");
+
+            ui.end_row();
+
+            ui.label("Projection").on_hover_text(
+                "The exponential map unwraps the view around its center, with the zoom deepening \
+                 towards the bottom",
+            );
+            egui::ComboBox::from_id_source("projection")
+                .selected_text(globals.projection.name())
+                .show_ui(ui, |ui| {
+                    for projection in Projection::ALL {
+                        ui.selectable_value(&mut globals.projection, projection, projection.name());
+                    }
+                });
+        });
+
+        draw_section(ui, "Anti-aliasing", |ui| {
+            ui.label("Samples")
+                .on_hover_text("Jittered samples per pixel, each rendered as a full frame");
+            egui::ComboBox::from_id_source("supersampling")
+                .selected_text(format!("{}x", globals.supersampling.pow(2)))
+                .show_ui(ui, |ui| {
+                    for grid_size in sampling::GRID_SIZES {
+                        let text = format!("{}x", grid_size.pow(2));
+                        ui.selectable_value(&mut globals.supersampling, grid_size, text);
+                    }
+                });
+
+            ui.end_row();
+
+            ui.label("Reconstruction").on_hover_text(
+                "Box averages the samples of a pixel, Gaussian also weighs in its neighbours",
+            );
+            egui::ComboBox::from_id_source("reconstruction")
+                .selected_text(globals.reconstruction.name())
+                .show_ui(ui, |ui| {
+                    for reconstruction in Reconstruction::ALL {
+                        ui.selectable_value(
+                            &mut globals.reconstruction,
+                            reconstruction,
+                            reconstruction.name(),
+                        );
+                    }
+                });
+
+            ui.end_row();
+
+            ui.label("Adaptive")
+                .on_hover_text("Render extra samples for pixels with a high local variance");
+            ui.checkbox(&mut globals.refine, "");
+
+            ui.end_row();
+
+            ui.label("Threshold")
+                .on_hover_text("Standard deviation above which a pixel is refined");
+            ui.add_enabled(
+                globals.refine,
+                egui::Slider::new(&mut globals.refine_threshold, 0.05..=4.0).logarithmic(true),
+            );
+
+            ui.end_row();
+
+            ui.label("Show refined");
+            ui.checkbox(&mut globals.show_refinement, "");
+        });
+
+        draw_section(ui, "Subdivision", |ui| {
+            ui.label("Enabled");
+            ui.checkbox(&mut globals.subdivision, "");
+
+            ui.end_row();
+
+            ui.label("Show filled");
+            ui.checkbox(&mut globals.show_subdivision, "");
+        });
+    }
+}
+
+impl ColouringPane {
+    const DEFAULT_PATH: &'static str = "palette.json";
+
+    fn new() -> Self {
+        Self {
+            path: String::from(Self::DEFAULT_PATH),
+            status: String::new(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, globals: &mut app::Globals) {
+        draw_section(ui, "Colouring", |ui| {
+            ui.label("Input");
+            egui::ComboBox::from_id_source("colouring")
+                .selected_text(globals.colouring.name())
+                .show_ui(ui, |ui| {
+                    for colouring in Colouring::ALL {
+                        ui.selectable_value(&mut globals.colouring, colouring, colouring.name());
+                    }
+                });
+
+            ui.end_row();
+
+            ui.label("Stripe density");
+            ui.add(
+                egui::DragValue::new(&mut globals.stripe_density)
+                    .speed(0.1)
+                    .clamp_range(0.0..=64.0),
+            );
+
+            ui.end_row();
+
+            ui.label("Equalize").on_hover_text(
+                "Map iteration counts through their cumulative distribution in the current frame",
+            );
+            ui.checkbox(&mut globals.equalize, "");
+
+            ui.end_row();
+
+            ui.label("Histogram");
+            draw_histogram(ui, &globals.histogram);
+        });
+
+        draw_section(ui, "Palette", |ui| {
+            let palette = &mut globals.palette;
+            ui.label("Gradient");
+            draw_gradient(ui, palette);
+
+            ui.end_row();
+
+            ui.label("Repeat");
+            ui.add(
+                egui::Slider::new(&mut palette.repeat, 0.1..=100.0)
+                    .logarithmic(true)
+                    .suffix("x"),
+            );
+
+            ui.end_row();
+
+            ui.label("Offset");
+            ui.add(egui::Slider::new(&mut palette.offset, 0.0..=1.0));
+
+            ui.end_row();
+
+            ui.label("Interior");
+            let mut interior = palette.interior.to_array();
+            if ui.color_edit_button_rgb(&mut interior).changed() {
+                palette.interior = interior.into();
+            }
+
+            ui.end_row();
+
+            ui.label("Presets");
+            ui.horizontal(|ui| {
+                if ui.button("Default").clicked() {
+                    *palette = Palette::default();
+                }
+
+                if ui.button("Greyscale").clicked() {
+                    *palette = Palette::greyscale();
+                }
+            });
+        });
+
+        draw_section(ui, "Stops", |ui| {
+            let palette = &mut globals.palette;
+            let removable = palette.stops.len() > 2;
+            let mut removed = None;
+            let mut moved = false;
+            for (index, stop) in palette.stops.iter_mut().enumerate() {
+                let response = ui.add(
+                    egui::DragValue::new(&mut stop.position)
+                        .speed(0.001)
+                        .clamp_range(0.0..=1.0),
+                );
+
+                // Only reorder once the user lets go, so the dragged stop stays in place
+                moved |= response.drag_released() || response.lost_focus();
+
+                ui.horizontal(|ui| {
+                    let mut colour = stop.colour.to_array();
+                    if ui.color_edit_button_rgb(&mut colour).changed() {
+                        stop.colour = colour.into();
+                    }
+
+                    if ui
+                        .add_enabled(removable, egui::Button::new("Remove"))
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+                });
+
+                ui.end_row();
+            }
+
+            if let Some(index) = removed {
+                palette.stops.remove(index);
+            }
+
+            if moved {
+                palette.sort();
+            }
+
+            if ui.button("Add stop").clicked() {
+                // Split the widest gap between two stops
+                let position = palette
+                    .stops
+                    .windows(2)
+                    .max_by(|a, b| {
+                        let gap = |w: &[Stop]| w[1].position - w[0].position;
+                        gap(a).total_cmp(&gap(b))
+                    })
+                    .map_or(0.5, |w| 0.5 * (w[0].position + w[1].position));
+
+                let colour = palette.gradient(position);
+                palette.stops.push(Stop { position, colour });
+                palette.sort();
+            }
+        });
+
+        draw_section(ui, "File", |ui| {
+            ui.label("Path").on_hover_text(
+                "Fractint .map and Ultra Fractal .ugr files can be imported, anything else is read and written as JSON",
+            );
+            ui.text_edit_singleline(&mut self.path);
+
+            ui.end_row();
+
+            ui.horizontal(|ui| {
+                if ui.button("Import").clicked() {
+                    self.status = match Palette::load(std::path::Path::new(&self.path)) {
+                        Ok(palette) => {
+                            globals.palette = palette;
+                            format!("Imported {}", self.path)
+                        }
+                        Err(e) => format!("Import failed: {}", e),
+                    };
+                }
+
+                if ui.button("Export").clicked() {
+                    self.status = match globals.palette.save(std::path::Path::new(&self.path)) {
+                        Ok(_) => format!("Exported to {}", self.path),
+                        Err(e) => format!("Export failed: {}", e),
+                    };
+                }
+            });
+
+            ui.label(&self.status);
+        });
+    }
+}
+
+impl TrapEditor {
+    fn ui(&mut self, ui: &mut egui::Ui, globals: &mut app::Globals) {
+        let mut removed = None;
+        for (index, trap) in globals.traps.iter_mut().enumerate() {
+            ui.push_id(index, |ui| {
+                ui.horizontal(|ui| {
+                    egui::ComboBox::from_id_source("kind")
+                        .selected_text(trap.kind.name())
+                        .show_ui(ui, |ui| {
+                            for kind in TrapKind::ALL {
+                                ui.selectable_value(&mut trap.kind, kind, kind.name());
+                            }
+                        });
+
+                    ui.add(
+                        egui::DragValue::new(&mut trap.position.x)
+                            .speed(0.001)
+                            .prefix("r "),
+                    );
+                    ui.add(
+                        egui::DragValue::new(&mut trap.position.y)
+                            .speed(0.001)
+                            .prefix("i "),
+                    );
+
+                    match trap.kind {
+                        TrapKind::Line | TrapKind::Cross => {
+                            ui.drag_angle(&mut trap.angle);
+                        }
+                        TrapKind::Circle => {
+                            ui.add(
+                                egui::DragValue::new(&mut trap.radius)
+                                    .speed(0.001)
+                                    .clamp_range(0.0..=f32::MAX)
+                                    .prefix("radius "),
+                            );
+                        }
+                        TrapKind::Point => {}
+                    }
+
+                    let placing = self.placing == Some(index);
+                    if ui.selectable_label(placing, "Place").clicked() {
+                        self.placing = if placing { None } else { Some(index) };
+                    }
+
+                    if ui.button("Remove").clicked() {
+                        removed = Some(index);
+                    }
+                });
+            });
+        }
+
+        if let Some(index) = removed {
+            globals.traps.remove(index);
+            self.placing = None;
+        }
+
+        ui.add_enabled_ui(globals.traps.len() < trap::MAX_TRAPS, |ui| {
+            if ui.button("Add trap").clicked() {
+                globals.traps.push(Trap::new(TrapKind::Point));
+            }
+        });
+    }
+}
+
+impl CertifiedPane {
+    const EXPORT_PATH: &'static str = "certified.ppm";
+
+    fn new() -> Self {
+        Self {
+            width: 320,
+            height: 180,
+            iterations: 1000,
+            max_period: 64,
+            job: None,
+            map: None,
+            status: String::new(),
+        }
+    }
+
+    fn ui(&mut self, ui: &mut egui::Ui, globals: &mut app::Globals) {
+        if self.job.as_ref().is_some_and(|job| job.is_finished()) {
+            match self.job.take().unwrap().join() {
+                Ok(map) => {
+                    self.status = String::from("Done");
+                    self.map = Some(map);
+                }
+                Err(_) => self.status = String::from("Render failed"),
+            }
+        }
+
+        let running = self.job.is_some();
+        if running {
+            ui.ctx().request_repaint();
+        }
+
+        draw_section(ui, "Parameters", |ui| {
+            ui.label("Resolution");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.width).clamp_range(1..=4096));
+                ui.label("x");
+                ui.add(egui::DragValue::new(&mut self.height).clamp_range(1..=4096));
+            });
+
+            ui.end_row();
+
+            ui.label("Iterations");
+            ui.add(egui::DragValue::new(&mut self.iterations).clamp_range(1..=100000));
+
+            ui.end_row();
+
+            ui.label("Max period");
+            ui.add(egui::DragValue::new(&mut self.max_period).clamp_range(1..=4096));
+        });
+
+        ui.horizontal(|ui| {
+            if ui
+                .add_enabled(!running, egui::Button::new("Render"))
+                .clicked()
+            {
+                let center = globals.center.clone();
+                let scale = globals.scale();
+                let parameters = certified::Parameters {
+                    width: self.width,
+                    height: self.height,
+                    iterations: self.iterations,
+                    max_period: self.max_period,
+                };
+
+                self.status = String::from("Rendering...");
+                self.job = Some(std::thread::spawn(move || {
+                    certified::render(&center, &scale, &parameters)
+                }));
+            }
+
+            if ui
+                .add_enabled(self.map.is_some(), egui::Button::new("Export"))
+                .clicked()
+            {
+                let map = self.map.as_ref().unwrap();
+                let result = std::fs::File::create(Self::EXPORT_PATH)
+                    .and_then(|mut file| map.write_ppm(&mut file));
+
+                self.status = match result {
+                    Ok(_) => format!("Exported to {}", Self::EXPORT_PATH),
+                    Err(e) => format!("Export failed: {}", e),
+                };
+            }
+
+            ui.label(&self.status);
+        });
+
+        if let Some(map) = &self.map {
+            draw_section(ui, "Result", |ui| {
+                for classification in [
+                    Classification::Exterior,
+                    Classification::Interior,
+                    Classification::Undecided,
+                ] {
+                    ui.label(format!("{:?}", classification));
+                    ui.label(
+                        egui::RichText::new(format!("{}", map.count(classification))).monospace(),
+                    );
+
+                    ui.end_row();
+                }
+
+                ui.label("Area");
+                ui.label(
+                    egui::RichText::new(format!(
+                        "[{:.6e}, {:.6e}]",
+                        map.area.lower.to_f64_round(rug::float::Round::Down),
+                        map.area.upper.to_f64_round(rug::float::Round::Up),
+                    ))
+                    .monospace(),
+                );
+
+                ui.end_row();
+
+                ui.label("Exterior area");
+                ui.label(
+                    egui::RichText::new(format!(
+                        "{:.6e} of {:.6e}",
+                        map.area.exterior.to_f64_round(rug::float::Round::Down),
+                        map.area.total.to_f64_round(rug::float::Round::Up),
+                    ))
+                    .monospace(),
+                );
+            });
+        }
     }
 }
 
@@ -133,12 +846,9 @@ impl LargeFloatEditor {
             if self.editing {
                 let lost_focus = ui.text_edit_singleline(&mut self.value).lost_focus();
                 if lost_focus && enter_pressed {
-                    match rug::Float::parse(&self.value) {
-                        Ok(f) => {
-                            float.assign(f.complete(PRECISION));
-                            self.editing = false;
-                        }
-                        Err(_) => {}
+                    if let Ok(f) = rug::Float::parse(&self.value) {
+                        float.assign(f.complete(PRECISION));
+                        self.editing = false;
                     }
                 } else if lost_focus {
                     self.editing = false;
@@ -167,3 +877,84 @@ where
                 });
         });
 }
+
+// Paints a strip previewing the gradient of a palette, ignoring repeat and offset
+fn draw_gradient(ui: &mut egui::Ui, palette: &Palette) {
+    const SEGMENTS: usize = 64;
+
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(200.0, 16.0), egui::Sense::hover());
+    let mut mesh = egui::Mesh::default();
+    for i in 0..=SEGMENTS {
+        let t = i as f32 / SEGMENTS as f32;
+        let [r, g, b] = palette.gradient(t).to_array();
+        let colour = egui::Color32::from(egui::Rgba::from_rgb(r, g, b));
+        let x = egui::lerp(rect.left()..=rect.right(), t);
+        mesh.colored_vertex(egui::pos2(x, rect.top()), colour);
+        mesh.colored_vertex(egui::pos2(x, rect.bottom()), colour);
+        if i > 0 {
+            let base = 2 * i as u32;
+            mesh.add_triangle(base - 2, base - 1, base);
+            mesh.add_triangle(base - 1, base, base + 1);
+        }
+    }
+
+    ui.painter().add(egui::Shape::mesh(mesh));
+}
+
+fn stage_colour(stage: Stage) -> egui::Color32 {
+    let hue = stage as usize as f32 / Stage::ALL.len() as f32;
+    egui::ecolor::Hsva::new(hue, 0.6, 0.9, 1.0).into()
+}
+
+// Paints the time of every frame as a bar of its stages stacked, scaled to the slowest frame
+fn draw_frame_times(ui: &mut egui::Ui, frames: &VecDeque<FrameTimes>) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(200.0, 60.0), egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    let max = frames.iter().map(|f| f.gpu()).fold(0.0, f32::max);
+    if max <= 0.0 {
+        return;
+    }
+
+    let width = rect.width() / profiler::HISTORY as f32;
+    for (i, frame) in frames.iter().enumerate() {
+        let left = rect.left() + i as f32 * width;
+        let mut bottom = rect.bottom();
+        for stage in Stage::ALL {
+            let Some(time) = frame.stages[stage as usize] else {
+                continue;
+            };
+
+            let top = bottom - rect.height() * time / max;
+            painter.rect_filled(
+                egui::Rect::from_min_max(egui::pos2(left, top), egui::pos2(left + width, bottom)),
+                0.0,
+                stage_colour(stage),
+            );
+            bottom = top;
+        }
+    }
+}
+
+// Paints the bin counts of the iteration histogram as bars, scaled to the fullest bin
+fn draw_histogram(ui: &mut egui::Ui, counts: &[u32]) {
+    let (rect, _) = ui.allocate_exact_size(egui::vec2(200.0, 60.0), egui::Sense::hover());
+    let painter = ui.painter();
+    painter.rect_filled(rect, 0.0, ui.visuals().extreme_bg_color);
+
+    let max = counts.iter().copied().max().unwrap_or(0).max(1) as f32;
+    let width = rect.width() / counts.len() as f32;
+    for (i, &count) in counts.iter().enumerate().filter(|(_, &c)| c > 0) {
+        let left = rect.left() + i as f32 * width;
+        let top = rect.bottom() - rect.height() * count as f32 / max;
+        painter.rect_filled(
+            egui::Rect::from_min_max(
+                egui::pos2(left, top),
+                egui::pos2(left + width, rect.bottom()),
+            ),
+            0.0,
+            ui.visuals().text_color(),
+        );
+    }
+}
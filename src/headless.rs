@@ -0,0 +1,334 @@
+use std::{
+    io::{BufRead, Write},
+    sync::Arc,
+};
+
+use glam::f32::Vec4;
+use rug::ops::CompleteRound;
+
+use crate::{
+    app, colouring,
+    engine::{self, cpu::CpuEngine, gpu::GpuEngine, Engine, EngineKind},
+    histogram,
+    precision::PRECISION,
+    statistics::{self, Statistics},
+    storage,
+};
+
+/*
+    Renders frames into memory without a window or surface, using the GPU engine when an adapter
+    exists and the CPU engine otherwise, or when asked for.
+*/
+
+pub struct Location {
+    pub center: rug::Complex,
+    pub zoom: rug::Float,
+}
+
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    // Linear RGBA colours, stored row by row from the top of the image
+    pub pixels: Vec<Vec4>,
+}
+
+pub struct Renderer {
+    engine: Box<dyn Engine>,
+    // Features of the device behind the GPU engine, empty on the CPU engine
+    features: wgpu::Features,
+    // Of the last rendered frame
+    statistics: Statistics,
+}
+
+impl Default for Location {
+    fn default() -> Self {
+        Self {
+            center: rug::Complex::parse(app::CENTER)
+                .expect("Unable to parse complex number")
+                .complete((PRECISION, PRECISION)),
+            zoom: rug::Float::with_val(PRECISION, app::ZOOM),
+        }
+    }
+}
+
+impl Image {
+    // Colours and resolves a frame on the CPU and flips its rows, the first of which is the bottom
+    // of the image
+    fn from_frame(
+        frame: &engine::Frame,
+        globals: &storage::ColouringGlobals,
+        lut: &[Vec4],
+    ) -> Self {
+        fn flip<T: Copy>(values: &[T], width: u32) -> Vec<T> {
+            values
+                .chunks_exact(width as usize)
+                .rev()
+                .flatten()
+                .copied()
+                .collect()
+        }
+
+        let cdf = histogram::cumulative(&histogram::accumulate(&frame.channels));
+        let colours: Vec<Vec4> = (0..frame.height)
+            .flat_map(|y| (0..frame.width).map(move |x| (x, y)))
+            .map(|(x, y)| colouring::resolve(globals, lut, &cdf, frame, x, y).extend(1.0))
+            .collect();
+
+        Self {
+            width: frame.width,
+            height: frame.height,
+            pixels: flip(&colours, frame.width),
+        }
+    }
+
+    // Encodes the colours as 8-bit sRGB, matching what the window presents
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let encode = |c: f32| {
+            let c = c.clamp(0.0, 1.0);
+            let srgb = if c <= 0.0031308 {
+                12.92 * c
+            } else {
+                1.055 * c.powf(1.0 / 2.4) - 0.055
+            };
+
+            (srgb * 255.0).round() as u8
+        };
+
+        self.pixels
+            .iter()
+            .flat_map(|c| c.to_array().map(encode))
+            .collect()
+    }
+
+    // Decodes an 8-bit binary PPM, as written by `write_ppm`, back into linear colours
+    pub fn read_ppm<R: BufRead>(reader: &mut R) -> Result<Self, String> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes).map_err(|e| e.to_string())?;
+
+        // The header is four whitespace separated fields, followed by a single whitespace byte
+        let mut fields = Vec::new();
+        let mut start = 0;
+        while fields.len() < 4 {
+            let rest = bytes.get(start..).ok_or("Truncated PPM header")?;
+            let skip = rest
+                .iter()
+                .position(|b| !b.is_ascii_whitespace())
+                .ok_or("Truncated PPM header")?;
+            let length = rest[skip..]
+                .iter()
+                .position(|b| b.is_ascii_whitespace())
+                .ok_or("Truncated PPM header")?;
+            fields.push(String::from_utf8_lossy(&rest[skip..skip + length]).into_owned());
+            start += skip + length + 1;
+        }
+
+        let parse = |field: &str| {
+            field
+                .parse::<u32>()
+                .map_err(|_| format!("Invalid PPM header field: {}", field))
+        };
+
+        if fields[0] != "P6" || parse(&fields[3])? != 255 {
+            return Err("Only 8-bit binary PPM images are supported".to_string());
+        }
+
+        let (width, height) = (parse(&fields[1])?, parse(&fields[2])?);
+        let length = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|pixels| pixels.checked_mul(3))
+            .ok_or("PPM image too large")?;
+        let data = bytes
+            .get(start..)
+            .and_then(|data| data.get(..length))
+            .ok_or("Truncated PPM data")?;
+
+        let decode = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+
+        let pixels = data
+            .chunks_exact(3)
+            .map(|c| Vec4::new(decode(c[0]), decode(c[1]), decode(c[2]), 1.0))
+            .collect();
+
+        Ok(Self {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        let bytes: Vec<u8> = self
+            .to_rgba8()
+            .chunks_exact(4)
+            .flat_map(|c| [c[0], c[1], c[2]])
+            .collect();
+
+        writer.write_all(&bytes)
+    }
+}
+
+impl Renderer {
+    // Renders on the requested engine, the GPU engine falls back to the CPU one without an adapter
+    pub async fn new(kind: EngineKind) -> Self {
+        let device = match kind {
+            EngineKind::Gpu => request_device().await,
+            EngineKind::Cpu => None,
+        };
+
+        let (engine, features): (Box<dyn Engine>, _) = match device {
+            Some((device, queue)) => {
+                let features = device.features();
+                let engine = GpuEngine::new(Arc::new(device), Arc::new(queue));
+                (Box::new(engine), features)
+            }
+            None => (Box::new(CpuEngine::new()), wgpu::Features::empty()),
+        };
+
+        Self {
+            engine,
+            features,
+            statistics: Statistics::default(),
+        }
+    }
+
+    pub fn engine(&self) -> &dyn Engine {
+        self.engine.as_ref()
+    }
+
+    pub fn features(&self) -> wgpu::Features {
+        self.features
+    }
+
+    pub fn statistics(&self) -> Statistics {
+        self.statistics
+    }
+
+    pub fn render(&mut self, location: &Location, width: u32, height: u32) -> Image {
+        let mut globals = app::Globals::new(location.center.clone(), location.zoom.clone());
+        globals.negotiate(self.features);
+        self.render_globals(&globals, width, height)
+    }
+
+    pub fn render_globals(&mut self, globals: &app::Globals, width: u32, height: u32) -> Image {
+        let mut frame = engine::Frame::default();
+        let view = globals.view(width, height);
+        self.engine
+            .render(&view, engine::Target::Memory(&mut frame));
+        self.statistics = statistics::accumulate(&frame.channels, view.iterations());
+        let lut = globals.palette.lut();
+        Image::from_frame(&frame, &globals.colouring_globals(height), &lut)
+    }
+}
+
+// A device on a hardware adapter, as software adapters are slower than the CPU engine
+async fn request_device() -> Option<(wgpu::Device, wgpu::Queue)> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    });
+
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::HighPerformance,
+            compatible_surface: None,
+            force_fallback_adapter: false,
+        })
+        .await
+        .filter(|adapter| adapter.get_info().device_type != wgpu::DeviceType::Cpu)?;
+
+    adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
+                    | (adapter.features() & wgpu::Features::SHADER_F64),
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        )
+        .await
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::palette::Palette;
+
+    #[test]
+    fn test_render() {
+        let mut renderer = pollster::block_on(Renderer::new(EngineKind::Gpu));
+        let location = Location {
+            center: rug::Complex::with_val(PRECISION, (-0.5, 0.0)),
+            zoom: rug::Float::with_val(PRECISION, -1.0),
+        };
+
+        let image = renderer.render(&location, 32, 24);
+        assert_eq!(image.pixels.len(), 32 * 24);
+
+        // The center is inside the main cardioid, while the corners escape almost immediately and
+        // take the dark blue at the start of the default palette
+        assert_eq!(image.pixels[12 * 32 + 16], Vec4::W);
+        assert!(image.pixels[0].x < 0.05 && image.pixels[0].z > 0.05);
+
+        // Orbits in the cardioid are attracted to its fixed point, and every sample counts once
+        let statistics = renderer.statistics();
+        assert!(statistics.interior > 0 && statistics.escaped > 0);
+        assert_eq!(statistics.samples() % (32 * 24), 0);
+    }
+
+    #[test]
+    fn test_rows_are_flipped() {
+        let escaped = storage::Channels {
+            flags: storage::Channels::ESCAPED,
+            ..Default::default()
+        };
+
+        let interior = storage::Channels::default();
+        let frame = engine::Frame {
+            width: 2,
+            height: 2,
+            channels: vec![escaped, escaped, interior, interior],
+            samples: vec![glam::f32::Vec2::ZERO],
+            refinement: Vec::new(),
+            refined: Vec::new(),
+        };
+
+        let mut globals = app::Globals::new(Location::default().center, Location::default().zoom);
+        globals.palette = Palette::greyscale();
+
+        let lut = globals.palette.lut();
+        let image = Image::from_frame(&frame, &globals.colouring_globals(2), &lut);
+        assert_eq!(image.pixels, [Vec4::ONE, Vec4::ONE, Vec4::W, Vec4::W]);
+        assert_eq!(image.to_rgba8()[..4], [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn test_ppm_round_trip() {
+        let image = Image {
+            width: 3,
+            height: 2,
+            pixels: (0..6)
+                .map(|i| Vec4::new(i as f32 / 5.0, 0.5, 1.0 - i as f32 / 5.0, 1.0))
+                .collect(),
+        };
+
+        let mut bytes = Vec::new();
+        image.write_ppm(&mut bytes).unwrap();
+        let read = Image::read_ppm(&mut bytes.as_slice()).unwrap();
+        assert_eq!((read.width, read.height), (3, 2));
+        assert_eq!(read.to_rgba8(), image.to_rgba8());
+
+        assert!(Image::read_ppm(&mut &bytes[..bytes.len() - 1]).is_err());
+        let huge = b"P6\n4294967295 4294967295\n255\n";
+        assert!(Image::read_ppm(&mut huge.as_slice()).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::storage;
+
+/*
+    Histogram of the normalized iteration counts of escaped pixels, and its cumulative
+    distribution used for histogram equalized colouring. The GPU computes the same thing in
+    histogram.wgsl, these are the CPU versions for frames that stay in memory.
+*/
+
+// Must be kept in sync with `BINS` in histogram.wgsl and colouring.wgsl
+pub const BINS: usize = 256;
+
+fn bin(iterations: f32) -> usize {
+    ((iterations * BINS as f32) as usize).min(BINS - 1)
+}
+
+pub fn accumulate(channels: &[storage::Channels]) -> Vec<u32> {
+    let mut counts = vec![0; BINS];
+    for channels in channels {
+        if channels.flags & storage::Channels::ESCAPED != 0 {
+            counts[bin(channels.iterations)] += 1;
+        }
+    }
+
+    counts
+}
+
+// Fraction of escaped pixels that fall into every bin or one below it
+pub fn cumulative(counts: &[u32]) -> Vec<f32> {
+    let total = counts.iter().sum::<u32>().max(1) as f32;
+    counts
+        .iter()
+        .scan(0, |sum, count| {
+            *sum += count;
+            Some(*sum as f32 / total)
+        })
+        .collect()
+}
+
+// Maps an iteration count through the distribution, interpolating within its bin
+pub fn equalize(cdf: &[f32], iterations: f32) -> f32 {
+    let position = iterations * BINS as f32;
+    let index = bin(iterations);
+    let lower = if index > 0 { cdf[index - 1] } else { 0.0 };
+    let fraction = (position - index as f32).clamp(0.0, 1.0);
+    lower + (cdf[index] - lower) * fraction
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_equalize() {
+        let channels: Vec<storage::Channels> = [0.1, 0.1, 0.1, 0.9, 1.0]
+            .iter()
+            .enumerate()
+            .map(|(i, &iterations)| storage::Channels {
+                iterations,
+                // The last pixel never escaped and is left out
+                flags: if i < 4 { storage::Channels::ESCAPED } else { 0 },
+                ..Default::default()
+            })
+            .collect();
+
+        let counts = accumulate(&channels);
+        assert_eq!(counts.iter().sum::<u32>(), 4);
+        assert_eq!(counts[bin(0.1)], 3);
+
+        let cdf = cumulative(&counts);
+        assert_eq!(cdf[BINS - 1], 1.0);
+
+        // Three quarters of the pixels sit in the first occupied bin, so it spans most of the range
+        let first = bin(0.1) as f32 / BINS as f32;
+        assert_eq!(equalize(&cdf, first), 0.0);
+        assert_eq!(equalize(&cdf, first + 1.0 / BINS as f32), 0.75);
+        assert_eq!(equalize(&cdf, 1.0), 1.0);
+    }
+}
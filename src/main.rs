@@ -1,11 +1,28 @@
 mod app;
+mod certified;
+mod colouring;
+mod df64;
+mod engine;
+mod exponential;
 mod gui;
+mod headless;
+mod histogram;
 mod mandelbrot;
+mod palette;
 mod pipeline;
 mod precision;
+mod profiler;
+mod reference;
+mod refinement;
+mod sampling;
+mod statistics;
 mod storage;
+mod subdivision;
+mod trap;
 
 use app::State;
+use encase::ShaderSize;
+use engine::EngineKind;
 use winit::{
     dpi::LogicalSize,
     event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent},
@@ -24,35 +41,31 @@ async fn run() -> Result<(), String> {
         .build(&event_loop)
         .map_err(|e| e.to_string())?;
 
-    let mut state = State::new(&window).await;
+    let mut state = State::new(&window).await?;
 
     event_loop.run(move |event, _, control_flow| match event {
         Event::WindowEvent {
             ref event,
             window_id,
-        } if window_id == window.id() => {
-            if !state.input(event) {
-                match event {
-                    WindowEvent::CloseRequested
-                    | WindowEvent::KeyboardInput {
-                        input:
-                            KeyboardInput {
-                                state: ElementState::Pressed,
-                                virtual_keycode: Some(VirtualKeyCode::Escape),
-                                ..
-                            },
+        } if window_id == window.id() && !state.input(event) => match event {
+            WindowEvent::CloseRequested
+            | WindowEvent::KeyboardInput {
+                input:
+                    KeyboardInput {
+                        state: ElementState::Pressed,
+                        virtual_keycode: Some(VirtualKeyCode::Escape),
                         ..
-                    } => *control_flow = ControlFlow::Exit,
-                    WindowEvent::Resized(physical_size) => {
-                        state.resize(*physical_size);
-                    }
-                    WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
-                        state.resize(**new_inner_size);
-                    }
-                    _ => {}
-                }
+                    },
+                ..
+            } => *control_flow = ControlFlow::Exit,
+            WindowEvent::Resized(physical_size) => {
+                state.resize(*physical_size);
             }
-        }
+            WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                state.resize(**new_inner_size);
+            }
+            _ => {}
+        },
         Event::RedrawRequested(window_id) if window_id == window.id() => {
             state.update();
             match state.render(&window) {
@@ -65,16 +78,191 @@ async fn run() -> Result<(), String> {
                 Err(e) => eprintln!("{:?}", e),
             }
         }
-        Event::MainEventsCleared => {
-            window.request_redraw();
+        // Only draw while something is changing, and sleep until the next event otherwise
+        Event::MainEventsCleared if *control_flow != ControlFlow::Exit => {
+            *control_flow = match state.next_redraw() {
+                Some(deadline) if deadline <= std::time::Instant::now() => {
+                    window.request_redraw();
+                    ControlFlow::Poll
+                }
+                Some(deadline) => ControlFlow::WaitUntil(deadline),
+                None => ControlFlow::Wait,
+            };
         }
         _ => {}
     });
 }
 
+fn parse_argument<T: std::str::FromStr>(value: &str, name: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("Invalid {}: {}", name, value))
+}
+
+// Frames have to fit the texture and storage buffer limits of every device, checked up front as
+// exceeding them panics in the middle of rendering
+fn check_dimensions(width: u32, height: u32) -> Result<(), String> {
+    let limits = wgpu::Limits::default();
+    let max_pixels =
+        limits.max_storage_buffer_binding_size as u64 / storage::Channels::SHADER_SIZE.get();
+
+    if width == 0 || height == 0 {
+        Err("Width and height must be at least 1".to_string())
+    } else if width.max(height) > limits.max_texture_dimension_2d
+        || width as u64 * height as u64 > max_pixels
+    {
+        Err(format!(
+            "{}x{} is too large, frames are limited to {} pixels a side and {} pixels in total",
+            width, height, limits.max_texture_dimension_2d, max_pixels
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+fn write_image(image: &headless::Image, path: &str) -> Result<(), String> {
+    let file = std::fs::File::create(path).map_err(|e| e.to_string())?;
+    image
+        .write_ppm(&mut std::io::BufWriter::new(file))
+        .map_err(|e| e.to_string())
+}
+
+// Splits off a leading `--cpu` flag, which renders on the CPU engine even when an adapter exists
+fn engine_argument(args: &[String]) -> (EngineKind, &[String]) {
+    match args.split_first() {
+        Some((flag, rest)) if flag == "--cpu" => (EngineKind::Cpu, rest),
+        _ => (EngineKind::Gpu, args),
+    }
+}
+
+// Usage: fractoscope --headless [--cpu] <width> <height> <output.ppm>
+fn render_headless(args: &[String]) -> Result<(), String> {
+    let (kind, args) = engine_argument(args);
+    let [width, height, path] = args else {
+        return Err(
+            "Usage: fractoscope --headless [--cpu] <width> <height> <output.ppm>".to_string(),
+        );
+    };
+
+    let width: u32 = parse_argument(width, "width")?;
+    let height: u32 = parse_argument(height, "height")?;
+    check_dimensions(width, height)?;
+
+    let mut renderer = pollster::block_on(headless::Renderer::new(kind));
+    let image = renderer.render(&headless::Location::default(), width, height);
+    write_image(&image, path)?;
+
+    println!(
+        "Rendered {}x{} on the {} to {}",
+        width,
+        height,
+        renderer.engine().kind().name(),
+        path
+    );
+
+    // One line of key=value pairs, for scripts to pick up
+    let statistics = renderer.statistics();
+    let (min_escape, max_escape) = statistics.escape_range.unwrap_or_default();
+    println!(
+        "Statistics: escaped={} interior={} max_iteration={} glitched={} min_escape={} \
+         max_escape={} skipped={:.2}",
+        statistics.escaped,
+        statistics.interior,
+        statistics.max_iteration,
+        statistics.glitched,
+        min_escape,
+        max_escape,
+        statistics.skipped
+    );
+    Ok(())
+}
+
+// Usage: fractoscope --exponential [--cpu] <width> <octaves> <output.ppm>
+fn render_exponential(args: &[String]) -> Result<(), String> {
+    let (kind, args) = engine_argument(args);
+    let [width, octaves, path] = args else {
+        return Err(
+            "Usage: fractoscope --exponential [--cpu] <width> <octaves> <output.ppm>".to_string(),
+        );
+    };
+
+    let width: u32 = parse_argument(width, "width")?;
+    let octaves: f32 = parse_argument(octaves, "octaves")?;
+    let height = exponential::strip_height(width, octaves);
+    check_dimensions(width, height)?;
+
+    let location = headless::Location::default();
+    let mut globals = app::Globals::new(location.center, location.zoom);
+    globals.projection = exponential::Projection::Exponential;
+
+    let mut renderer = pollster::block_on(headless::Renderer::new(kind));
+    globals.negotiate(renderer.features());
+    let image = renderer.render_globals(&globals, width, height);
+    write_image(&image, path)?;
+
+    println!(
+        "Rendered a {}x{} exponential map of {:.1} octaves on the {} to {}",
+        width,
+        height,
+        exponential::octaves(width, height),
+        renderer.engine().kind().name(),
+        path
+    );
+    Ok(())
+}
+
+// Usage: fractoscope --assemble <strip.ppm> <width> <height> <frames> <directory>
+fn assemble_frames(args: &[String]) -> Result<(), String> {
+    let [strip, width, height, frames, directory] = args else {
+        return Err(
+            "Usage: fractoscope --assemble <strip.ppm> <width> <height> <frames> <directory>"
+                .to_string(),
+        );
+    };
+
+    let width: u32 = parse_argument(width, "width")?;
+    let height: u32 = parse_argument(height, "height")?;
+    let frames: u32 = parse_argument(frames, "frame count")?;
+    check_dimensions(width, height)?;
+
+    let file = std::fs::File::open(strip).map_err(|e| e.to_string())?;
+    let strip = headless::Image::read_ppm(&mut std::io::BufReader::new(file))?;
+    std::fs::create_dir_all(directory).map_err(|e| e.to_string())?;
+
+    // Frames are spaced evenly in octaves, for a zoom at constant speed
+    let depth = exponential::max_depth(&strip, width, height);
+    if depth < 0.0 {
+        return Err(format!(
+            "The strip covers {:.1} octaves too few for frames of this height",
+            -depth
+        ));
+    }
+
+    for i in 0..frames {
+        let t = i as f32 / (frames - 1).max(1) as f32;
+        let frame = exponential::assemble(&strip, width, height, t * depth)?;
+        let path = std::path::Path::new(directory).join(format!("frame_{:05}.ppm", i));
+        write_image(&frame, &path.to_string_lossy())?;
+    }
+
+    println!(
+        "Assembled {} frames of {}x{} zooming {:.1} octaves into {}",
+        frames, width, height, depth, directory
+    );
+    Ok(())
+}
+
 fn main() {
     env_logger::init();
-    match pollster::block_on(run()) {
+    let args: Vec<String> = std::env::args().collect();
+    let result = match args.get(1).map(String::as_str) {
+        Some("--headless") => render_headless(&args[2..]),
+        Some("--exponential") => render_exponential(&args[2..]),
+        Some("--assemble") => assemble_frames(&args[2..]),
+        _ => pollster::block_on(run()),
+    };
+
+    match result {
         Ok(_) => {}
         Err(e) => {
             eprintln!("{}", e);
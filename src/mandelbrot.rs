@@ -1,23 +1,58 @@
 use std::ops::AddAssign;
 
-pub fn compute_reference_orbit<const N: usize>(
+// A reference orbit rounded to single precision, and to double precision for the native kernel
+pub struct Orbit {
+    pub points: Vec<glam::f32::Vec2>,
+    // Left empty unless asked for
+    pub points_f64: Vec<glam::f64::DVec2>,
+    // Iteration at which the orbit left the radius, past which it repeats its last point
+    pub escaped: Option<usize>,
+}
+
+// Iterates the orbit once at arbitrary precision, rounding every point to each of the types. An
+// orbit that escapes is of no use as a reference, so it stops there but keeps its full length, as
+// the kernels find the orbit of every reference at a multiple of the iteration count. The kernels
+// flag samples still bounded once the reference left the radius as glitched.
+pub fn compute_reference_orbit(
     z: rug::Complex,
     c: rug::Complex,
     radius: rug::Float,
-) -> [glam::f32::Vec2; N] {
+    iterations: usize,
+    double: bool,
+) -> Orbit {
     let rsqr = radius.square();
-    let mut orbit: [glam::f32::Vec2; N] = [glam::f32::Vec2::ZERO; N];
+    let mut orbit = Orbit {
+        points: Vec::with_capacity(iterations),
+        points_f64: Vec::with_capacity(if double { iterations } else { 0 }),
+        escaped: None,
+    };
+
     let mut z = z;
     // let mut z = rug::Complex::parse("(-3.499370100999999999999999999999999999996e-1 -4.293244312274789964509138456000000000002e-1)").unwrap().complete((128, 128));
-    for i in 0..N {
+    for i in 0..iterations {
         z.square_mut();
         z.add_assign(&c);
 
+        orbit
+            .points
+            .push((z.real().to_f32(), z.imag().to_f32()).into());
+        if double {
+            orbit
+                .points_f64
+                .push((z.real().to_f64(), z.imag().to_f64()).into());
+        }
+
         if (z.real().clone().square()) + (z.imag().clone().square()) > rsqr {
-            println!("Invalid reference point after {} iterations: {}", i, z);
+            orbit.escaped = Some(i);
+            break;
         }
+    }
 
-        orbit[i] = (z.real().to_f32(), z.imag().to_f32()).into();
+    if let Some(&last) = orbit.points.last() {
+        orbit.points.resize(iterations, last);
+    }
+    if let Some(&last) = orbit.points_f64.last() {
+        orbit.points_f64.resize(iterations, last);
     }
     orbit
 }
@@ -40,7 +75,6 @@ pub fn compute_series_coefficients(
         *precisions.iter().max().unwrap()
     };
 
-    let rsqr = radius.square();
     let mut z = z;
     let mut coefficients = [
         rug::Complex::with_val(p, (1.0, 0.0)),
@@ -49,14 +83,10 @@ pub fn compute_series_coefficients(
         rug::Complex::with_val(p, (0.0, 0.0)),
     ];
 
-    for i in 0..iterations {
+    for _ in 0..iterations {
         z.square_mut();
         z.add_assign(&c);
 
-        if (z.real().clone().square()) + (z.imag().clone().square()) > rsqr {
-            println!("Invalid reference point after {} iterations: {}", i, z);
-        }
-
         let z2 = z.clone().square();
         let [a, b, c, d] = coefficients;
         coefficients = [
@@ -89,12 +119,32 @@ mod tests {
     fn test_compute_reference_orbit() {
         let c = rug::Complex::with_val(PRECISION, (0.5, 0.0));
         let z = rug::Complex::with_val(PRECISION, (0.5, 0.0));
-        let orbits = compute_reference_orbit::<1>(z, c, rug::Float::with_val(PRECISION, 2.0));
-        assert_eq!(orbits[0], (0.75, 0.0).into());
+        let orbit = compute_reference_orbit(z, c, rug::Float::with_val(PRECISION, 2.0), 1, false);
+        assert_eq!(orbit.points[0], (0.75, 0.0).into());
+        assert!(orbit.points_f64.is_empty());
 
         let c = rug::Complex::with_val(PRECISION, (0.5, 0.0));
         let z = rug::Complex::with_val(PRECISION, (0.5001, 0.001));
-        let orbits = compute_reference_orbit::<1>(z, c, rug::Float::with_val(PRECISION, 2.0));
-        assert_eq!(orbits[0], (0.750099, 0.0010002).into());
+        let orbit = compute_reference_orbit(z, c, rug::Float::with_val(PRECISION, 2.0), 1, true);
+        assert_eq!(orbit.points[0], (0.750099, 0.0010002).into());
+        // Input values are only exact to the 64 bits of their precision
+        assert!(orbit.points_f64[0].abs_diff_eq((0.75009901, 0.0010002).into(), 1e-15));
+    }
+
+    #[test]
+    fn test_escaped_orbit() {
+        // 0, 1, 2, 5 leaves the radius on the third iteration
+        let c = rug::Complex::with_val(PRECISION, (1.0, 0.0));
+        let z = rug::Complex::with_val(PRECISION, (0.0, 0.0));
+        let orbit = compute_reference_orbit(z, c, rug::Float::with_val(PRECISION, 2.0), 8, true);
+        assert_eq!(orbit.escaped, Some(2));
+        assert_eq!(orbit.points.len(), 8);
+        assert_eq!(orbit.points_f64.len(), 8);
+        assert!(orbit.points[2..].iter().all(|&z| z == (5.0, 0.0).into()));
+
+        let c = rug::Complex::with_val(PRECISION, (-1.0, 0.0));
+        let z = rug::Complex::with_val(PRECISION, (0.0, 0.0));
+        let orbit = compute_reference_orbit(z, c, rug::Float::with_val(PRECISION, 2.0), 8, false);
+        assert_eq!(orbit.escaped, None);
     }
 }
@@ -0,0 +1,339 @@
+use std::path::Path;
+
+use glam::f32::{vec3, Mat3, Vec3, Vec4};
+use serde::{Deserialize, Serialize};
+
+/*
+    Colour gradients applied by the colouring pass. Stops are stored in linear sRGB and blended in
+    OKLab, then baked into a lookup table that the shader samples.
+*/
+
+// Entries in the lookup table, must be kept in sync with the texture created in app.rs
+pub const LUT_SIZE: usize = 1024;
+
+// Positions of Ultra Fractal gradient stops run from 0 to 400
+const UGR_LENGTH: f32 = 400.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stop {
+    pub position: f32,
+    pub colour: Vec3,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Palette {
+    pub stops: Vec<Stop>,
+    // How often the gradient repeats over the colouring input, and where it starts
+    pub repeat: f32,
+    pub offset: f32,
+    // Used for pixels that never escape, when the colouring has no value for them
+    pub interior: Vec3,
+}
+
+/*
+    OKLab, see https://bottosson.github.io/posts/oklab/
+*/
+
+const LINEAR_TO_LMS: Mat3 = Mat3::from_cols(
+    vec3(0.41222146, 0.2119035, 0.08830246),
+    vec3(0.53633255, 0.6806995, 0.28171885),
+    vec3(0.051445995, 0.10739696, 0.6299787),
+);
+
+const LMS_TO_OKLAB: Mat3 = Mat3::from_cols(
+    vec3(0.21045426, 1.9779985, 0.025904037),
+    vec3(0.7936178, -2.4285922, 0.78277177),
+    vec3(-0.004072047, 0.4505937, -0.80867577),
+);
+
+const OKLAB_TO_LMS: Mat3 = Mat3::from_cols(
+    vec3(1.0, 1.0, 1.0),
+    vec3(0.39633778, -0.105561346, -0.08948418),
+    vec3(0.21580376, -0.06385417, -1.2914855),
+);
+
+const LMS_TO_LINEAR: Mat3 = Mat3::from_cols(
+    vec3(4.0767417, -1.268438, -0.0041960864),
+    vec3(-3.3077116, 2.6097574, -0.7034186),
+    vec3(0.23096994, -0.34131938, 1.7076147),
+);
+
+pub fn oklab_from_linear(colour: Vec3) -> Vec3 {
+    let lms = LINEAR_TO_LMS * colour;
+    LMS_TO_OKLAB * Vec3::from(lms.to_array().map(f32::cbrt))
+}
+
+pub fn linear_from_oklab(lab: Vec3) -> Vec3 {
+    let lms = OKLAB_TO_LMS * lab;
+    LMS_TO_LINEAR * (lms * lms * lms)
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn from_srgb8(r: u8, g: u8, b: u8) -> Vec3 {
+    Vec3::from([r, g, b].map(|c| srgb_to_linear(c as f32 / 255.0)))
+}
+
+/*
+    Palette
+*/
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            stops: vec![
+                Stop::srgb(0.0, 0, 7, 100),
+                Stop::srgb(0.16, 32, 107, 203),
+                Stop::srgb(0.42, 237, 255, 255),
+                Stop::srgb(0.6425, 255, 170, 0),
+                Stop::srgb(0.8575, 0, 2, 0),
+                Stop::srgb(1.0, 0, 7, 100),
+            ],
+            repeat: 1.0,
+            offset: 0.0,
+            interior: Vec3::ZERO,
+        }
+    }
+}
+
+impl Stop {
+    fn srgb(position: f32, r: u8, g: u8, b: u8) -> Self {
+        Self {
+            position,
+            colour: from_srgb8(r, g, b),
+        }
+    }
+}
+
+impl Palette {
+    pub fn greyscale() -> Self {
+        Self {
+            stops: vec![Stop::srgb(0.0, 0, 0, 0), Stop::srgb(1.0, 255, 255, 255)],
+            interior: Vec3::ONE,
+            ..Default::default()
+        }
+    }
+
+    // Colour of the gradient at a position between 0 and 1, before repeat and offset
+    pub fn gradient(&self, t: f32) -> Vec3 {
+        let Some(first) = self.stops.first() else {
+            return Vec3::ZERO;
+        };
+
+        let last = self.stops.last().unwrap();
+        if t <= first.position {
+            return first.colour;
+        }
+
+        let Some(i) = self.stops.iter().position(|stop| stop.position > t) else {
+            return last.colour;
+        };
+
+        let (a, b) = (self.stops[i - 1], self.stops[i]);
+        let fraction = (t - a.position) / (b.position - a.position);
+        let lab = oklab_from_linear(a.colour).lerp(oklab_from_linear(b.colour), fraction);
+        linear_from_oklab(lab).clamp(Vec3::ZERO, Vec3::ONE)
+    }
+
+    pub fn lut(&self) -> Vec<Vec4> {
+        (0..LUT_SIZE)
+            .map(|i| self.gradient(i as f32 / (LUT_SIZE - 1) as f32).extend(1.0))
+            .collect()
+    }
+
+    // Keeps stops ordered after they have been moved or added
+    pub fn sort(&mut self) {
+        self.stops.sort_by(|a, b| a.position.total_cmp(&b.position));
+    }
+
+    /*
+        Import and export
+    */
+
+    // Fractint palettes list one "r g b" triplet per line, anything after it is a comment
+    pub fn parse_map(text: &str) -> Result<Self, String> {
+        let colours = text
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| {
+                let values: Vec<u8> = line
+                    .split_whitespace()
+                    .take(3)
+                    .map(|value| value.parse::<u8>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Invalid palette entry: {}", line))?;
+
+                match values[..] {
+                    [r, g, b] => Ok(from_srgb8(r, g, b)),
+                    _ => Err(format!("Invalid palette entry: {}", line)),
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if colours.len() < 2 {
+            return Err(String::from("A palette needs at least two colours"));
+        }
+
+        let last = (colours.len() - 1) as f32;
+        Ok(Self {
+            stops: colours
+                .into_iter()
+                .enumerate()
+                .map(|(i, colour)| Stop {
+                    position: i as f32 / last,
+                    colour,
+                })
+                .collect(),
+            ..Default::default()
+        })
+    }
+
+    // Reads the first gradient of an Ultra Fractal gradient file, whose stops are written as
+    // "index=<0..400> color=<0xBBGGRR as decimal>" pairs
+    pub fn parse_ugr(text: &str) -> Result<Self, String> {
+        let gradient = text
+            .split_once("gradient:")
+            .map(|(_, rest)| rest)
+            .ok_or_else(|| String::from("No gradient found"))?;
+
+        let gradient = gradient
+            .split('}')
+            .next()
+            .unwrap_or_default()
+            .split("opacity:")
+            .next()
+            .unwrap_or_default();
+
+        let mut index = None;
+        let mut stops = Vec::new();
+        for token in gradient.split_whitespace() {
+            let Some((key, value)) = token.split_once('=') else {
+                continue;
+            };
+
+            let parse = |value: &str| {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| format!("Invalid gradient entry: {}", token))
+            };
+
+            match key {
+                "index" => index = Some(parse(value)?),
+                "color" => {
+                    let index = index
+                        .take()
+                        .ok_or_else(|| format!("Colour without index: {}", token))?;
+                    let [r, g, b, _] = parse(value)?.to_le_bytes();
+                    stops.push(Stop {
+                        position: (index as f32 / UGR_LENGTH).clamp(0.0, 1.0),
+                        colour: from_srgb8(r, g, b),
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        if stops.is_empty() {
+            return Err(String::from("The gradient has no colours"));
+        }
+
+        let mut palette = Self {
+            stops,
+            ..Default::default()
+        };
+
+        palette.sort();
+        Ok(palette)
+    }
+
+    pub fn parse_json(text: &str) -> Result<Self, String> {
+        let mut palette: Self = serde_json::from_str(text).map_err(|e| e.to_string())?;
+        palette.sort();
+        Ok(palette)
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Unable to serialize palette")
+    }
+
+    // Picks the format from the file extension, falling back to JSON
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("map") => Self::parse_map(&text),
+            Some("ugr") => Self::parse_ugr(&text),
+            _ => Self::parse_json(&text),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        std::fs::write(path, self.to_json()).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oklab_round_trip() {
+        let colour = vec3(0.2, 0.5, 0.9);
+        let round_trip = linear_from_oklab(oklab_from_linear(colour));
+        assert!(round_trip.abs_diff_eq(colour, 1e-4));
+
+        // White has no chroma and unit lightness
+        assert!(oklab_from_linear(Vec3::ONE).abs_diff_eq(Vec3::X, 1e-4));
+    }
+
+    #[test]
+    fn test_gradient() {
+        let palette = Palette::greyscale();
+        assert_eq!(palette.gradient(-1.0), Vec3::ZERO);
+        assert_eq!(palette.gradient(2.0), Vec3::ONE);
+
+        // Perceptual blending keeps the midpoint neutral but brighter than linear blending
+        let middle = palette.gradient(0.5);
+        assert!((middle.x - middle.z).abs() < 1e-4);
+        assert!(middle.x > 0.1 && middle.x < 0.5);
+    }
+
+    #[test]
+    fn test_parse_map() {
+        let palette = Palette::parse_map("0 0 0 black\n255 255 255\n\n0 0 255 blue\n").unwrap();
+        assert_eq!(palette.stops.len(), 3);
+        assert_eq!(palette.stops[1].position, 0.5);
+        assert_eq!(palette.stops[2].colour, Vec3::Z);
+
+        assert!(Palette::parse_map("0 0\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_ugr() {
+        let text = "Default {\n\
+            gradient:\n  title=\"Default\" smooth=yes\n  index=0 color=255\n  index=400 color=16711680\n\
+            opacity:\n  smooth=no index=0 opacity=255\n}\n";
+
+        let palette = Palette::parse_ugr(text).unwrap();
+        assert_eq!(palette.stops.len(), 2);
+        assert_eq!(palette.stops[0].colour, Vec3::X);
+        assert_eq!(palette.stops[1].position, 1.0);
+        assert_eq!(palette.stops[1].colour, Vec3::Z);
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let palette = Palette {
+            repeat: 3.0,
+            offset: 0.25,
+            ..Default::default()
+        };
+
+        assert_eq!(Palette::parse_json(&palette.to_json()).unwrap(), palette);
+    }
+}
@@ -1,2 +1,4 @@
+pub mod colouring;
 pub mod compute;
+pub mod histogram;
 pub mod render;
@@ -0,0 +1,288 @@
+
+// ======================== Structs =======================
+
+struct ColouringGlobals {
+    colouring: u32,
+    equalize: u32,
+    reconstruction: u32,
+    show_subdivision: u32,
+    show_refinement: u32,
+    pixel_size: f32,
+    palette_repeat: f32,
+    palette_offset: f32,
+    interior: vec3<f32>,
+}
+
+// Must be kept in sync with `Channels` in compute.wgsl
+struct Channels {
+    iterations: f32,
+    trap_distance: f32,
+    trap_iteration: f32,
+    triangle_inequality: f32,
+    stripe: f32,
+    curvature: f32,
+    final_z: vec2<f32>,
+    distance: f32,
+    flags: u32,
+}
+
+const FLAG_ESCAPED: u32 = 1u;
+const FLAG_FILLED: u32 = 2u;
+const FLAG_PENDING: u32 = 4u;
+
+// Must be kept in sync with `BINS` in histogram.rs
+const BINS: u32 = 256u;
+
+struct ChannelBuffer {
+    channels: array<Channels>,
+}
+
+// Must be kept in sync with `GRID_SIZE` in refinement.rs
+const REFINE_GRID_SIZE: u32 = 3u;
+const REFINE_SAMPLES: u32 = 9u;
+
+// ======================= Colouring ======================
+
+// Input of the palette for the selected colouring
+fn value(channels: Channels) -> f32 {
+    switch globals.colouring {
+        // Trap distance
+        case 1u: {
+            return exp(-4.0 * channels.trap_distance);
+        }
+        // Trap iteration
+        case 2u: {
+            return channels.trap_iteration;
+        }
+        // Triangle inequality average
+        case 3u: {
+            return channels.triangle_inequality;
+        }
+        // Stripe average
+        case 4u: {
+            return channels.stripe;
+        }
+        // Curvature average
+        case 5u: {
+            return channels.curvature;
+        }
+        // Distance estimate, measured in pixels
+        case 6u: {
+            return clamp(channels.distance / (4.0 * globals.pixel_size), 0.0, 1.0);
+        }
+        // Iterations
+        default: {
+            if (globals.equalize != 0u) {
+                return equalize(channels.iterations);
+            }
+
+            return channels.iterations;
+        }
+    }
+}
+
+// Maps an iteration count through the cumulative distribution, interpolating within its bin
+fn equalize(iterations: f32) -> f32 {
+    let position = iterations * f32(BINS);
+    let index = min(u32(position), BINS - 1u);
+    var lower = 0.0;
+    if (index > 0u) {
+        lower = cdf[index - 1u];
+    }
+
+    return mix(lower, cdf[index], clamp(position - f32(index), 0.0, 1.0));
+}
+
+// Iteration counts and distances are meaningless for pixels that never escaped
+fn is_interior(channels: Channels) -> bool {
+    let escaped = (channels.flags & FLAG_ESCAPED) != 0u;
+    return !escaped && (globals.colouring == 0u || globals.colouring == 6u);
+}
+
+// Samples the lookup table with linear interpolation, repeating it along the input
+fn palette_colour(value: f32) -> vec3<f32> {
+    let size = textureDimensions(palette);
+    let t = fract(value * globals.palette_repeat + globals.palette_offset);
+    let position = t * f32(size - 1u);
+    let index = min(u32(position), size - 1u);
+    let a = textureLoad(palette, index, 0).rgb;
+    let b = textureLoad(palette, min(index + 1u, size - 1u), 0).rgb;
+    return mix(a, b, fract(position));
+}
+
+fn colour(channels: Channels) -> vec3<f32> {
+    if (is_interior(channels)) {
+        return globals.interior;
+    }
+
+    return palette_colour(value(channels));
+}
+
+// Colour of one sample, tinted when subdivision filled it
+fn sample_colour(pixel: vec2<u32>, plane: u32, dimensions: vec2<u32>) -> vec3<f32> {
+    let channels = channel_buffer.channels[(plane * dimensions.y + pixel.y) * dimensions.x + pixel.x];
+    let color = colour(channels);
+    if ((channels.flags & FLAG_FILLED) != 0u && globals.show_subdivision != 0u) {
+        return mix(color, vec3<f32>(1.0, 0.0, 0.0), 0.5);
+    }
+
+    return color;
+}
+
+// Whether the engine has yet to reach any sample of a pixel since the view changed
+fn is_pending(pixel: vec2<u32>, dimensions: vec2<u32>) -> bool {
+    let planes = arrayLength(&sample_offsets);
+    for (var plane = 0u; plane < planes; plane += 1u) {
+        let channels = channel_buffer.channels[(plane * dimensions.y + pixel.y) * dimensions.x + pixel.x];
+        if ((channels.flags & FLAG_PENDING) != 0u) {
+            return true;
+        }
+    }
+
+    return false;
+}
+
+// Colour of one extra sample of a refined pixel, whose slot is offset by one
+fn refined_colour(slot: u32, sample: u32) -> vec3<f32> {
+    return colour(refined_buffer.channels[(slot - 1u) * REFINE_SAMPLES + sample]);
+}
+
+// Offset of an extra sample from the corner of its pixel
+fn refine_offset(sample: u32) -> vec2<f32> {
+    let cell = vec2<f32>(f32(sample % REFINE_GRID_SIZE), f32(sample / REFINE_GRID_SIZE));
+    return (cell + 0.5) / f32(REFINE_GRID_SIZE);
+}
+
+fn refinement_slot(pixel: vec2<u32>, dimensions: vec2<u32>) -> u32 {
+    return refinement_map[pixel.y * dimensions.x + pixel.x];
+}
+
+// ==================== Reconstruction ====================
+
+// Must be kept in sync with `GAUSSIAN_SIGMA` in colouring.rs
+const GAUSSIAN_SIGMA: f32 = 0.5;
+
+// Averages the samples inside the pixel, including the extra ones of refined pixels
+fn resolve_box(pixel: vec2<u32>, dimensions: vec2<u32>) -> vec3<f32> {
+    let planes = arrayLength(&sample_offsets);
+    var sum = vec3<f32>(0.0);
+    var count = f32(planes);
+    for (var plane = 0u; plane < planes; plane += 1u) {
+        sum += sample_colour(pixel, plane, dimensions);
+    }
+
+    let slot = refinement_slot(pixel, dimensions);
+    if (slot != 0u) {
+        for (var sample = 0u; sample < REFINE_SAMPLES; sample += 1u) {
+            sum += refined_colour(slot, sample);
+        }
+
+        count += f32(REFINE_SAMPLES);
+    }
+
+    return sum / count;
+}
+
+fn gaussian_weight(offset: vec2<f32>) -> f32 {
+    return exp(-dot(offset, offset) / (2.0 * GAUSSIAN_SIGMA * GAUSSIAN_SIGMA));
+}
+
+// Weighs the samples of the pixel and its neighbours by their distance to the pixel center
+fn resolve_gaussian(pixel: vec2<u32>, dimensions: vec2<u32>) -> vec3<f32> {
+    let planes = arrayLength(&sample_offsets);
+    var sum = vec3<f32>(0.0);
+    var weights = 0.0;
+    for (var dy = -1; dy <= 1; dy += 1) {
+        for (var dx = -1; dx <= 1; dx += 1) {
+            let neighbour = vec2<i32>(pixel) + vec2<i32>(dx, dy);
+            if (any(neighbour < vec2<i32>(0)) || any(neighbour >= vec2<i32>(dimensions))) {
+                continue;
+            }
+
+            if (is_pending(vec2<u32>(neighbour), dimensions)) {
+                continue;
+            }
+
+            let corner = vec2<f32>(f32(dx), f32(dy)) - 0.5;
+            for (var plane = 0u; plane < planes; plane += 1u) {
+                let weight = gaussian_weight(corner + sample_offsets[plane]);
+                sum += weight * sample_colour(vec2<u32>(neighbour), plane, dimensions);
+                weights += weight;
+            }
+
+            let slot = refinement_slot(vec2<u32>(neighbour), dimensions);
+            if (slot != 0u) {
+                for (var sample = 0u; sample < REFINE_SAMPLES; sample += 1u) {
+                    let weight = gaussian_weight(corner + refine_offset(sample));
+                    sum += weight * refined_colour(slot, sample);
+                    weights += weight;
+                }
+            }
+        }
+    }
+
+    return sum / weights;
+}
+
+// ========================= Main =========================
+
+@group(0) @binding(0)
+var<uniform> globals: ColouringGlobals;
+
+@group(0) @binding(1)
+var<storage, read> channel_buffer: ChannelBuffer;
+
+@group(0) @binding(2)
+var tex: texture_storage_2d<rgba32float, write>;
+
+@group(0) @binding(3)
+var palette: texture_1d<f32>;
+
+@group(0) @binding(4)
+var<storage, read> cdf: array<f32, BINS>;
+
+// Must match the offsets the channels were rendered with
+@group(0) @binding(5)
+var<storage, read> sample_offsets: array<vec2<f32>>;
+
+// Slot of every pixel plus one, or zero for pixels that were not refined, see refinement.rs
+@group(0) @binding(6)
+var<storage, read> refinement_map: array<u32>;
+
+@group(0) @binding(7)
+var<storage, read> refined_buffer: ChannelBuffer;
+
+@compute
+@workgroup_size(8, 8, 1)
+fn main(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>
+) {
+    let dimensions = textureDimensions(tex);
+    let pixel = g_invocation_id.xy;
+    if (any(pixel >= dimensions)) {
+        return;
+    }
+
+    // Left transparent, so the render pass shows the placeholder instead
+    if (is_pending(pixel, dimensions)) {
+        textureStore(tex, pixel, vec4<f32>(0.0));
+        return;
+    }
+
+    var color: vec3<f32>;
+    switch globals.reconstruction {
+        case 1u: {
+            color = resolve_gaussian(pixel, dimensions);
+        }
+        default: {
+            color = resolve_box(pixel, dimensions);
+        }
+    }
+
+    // Highlights the pixels that received extra samples
+    if (globals.show_refinement != 0u && refinement_slot(pixel, dimensions) != 0u) {
+        color = mix(color, vec3<f32>(0.0, 1.0, 0.0), 0.5);
+    }
+
+    textureStore(tex, pixel, vec4<f32>(color, 1.0));
+}
@@ -1,13 +1,32 @@
+use encase::ShaderType;
+
+use crate::{reference, storage};
+
 pub struct ComputePipeline {
     pub pipeline: wgpu::ComputePipeline,
+    pub subdivide_pipeline: wgpu::ComputePipeline,
+    pub clear_pipeline: wgpu::ComputePipeline,
+    pub detect_pipeline: wgpu::ComputePipeline,
+    pub refine_pipeline: wgpu::ComputePipeline,
     pub bind_group_layout: wgpu::BindGroupLayout,
 }
 
 impl ComputePipeline {
     pub fn new(device: &wgpu::Device) -> Self {
+        // The native steps only compile on devices with f64 support, elsewhere they are stubbed
         let module = {
-            let descriptor = wgpu::include_wgsl!("compute.wgsl");
-            device.create_shader_module(descriptor)
+            let native = if device.features().contains(wgpu::Features::SHADER_F64) {
+                include_str!("native_f64.wgsl")
+            } else {
+                include_str!("native_stub.wgsl")
+            };
+
+            device.create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("compute.wgsl"),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!("{}\n{}", include_str!("compute.wgsl"), native).into(),
+                ),
+            })
         };
 
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -35,14 +54,113 @@ impl ComputePipeline {
                     },
                     count: None,
                 },
-                // Render texture
+                // Orbit traps
                 wgpu::BindGroupLayoutEntry {
                     binding: 2,
                     visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        format: wgpu::TextureFormat::Rgba32Float,
-                        access: wgpu::StorageTextureAccess::ReadWrite,
-                        view_dimension: wgpu::TextureViewDimension::D2,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Output channels
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Tile, offset for every dispatch
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: Some(storage::Tile::min_size()),
+                    },
+                    count: None,
+                },
+                // Sample offsets
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Refinement list
+                wgpu::BindGroupLayoutEntry {
+                    binding: 6,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Refinement map
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Refined channels
+                wgpu::BindGroupLayoutEntry {
+                    binding: 8,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // Reference orbit in f64, only read by the native kernel
+                wgpu::BindGroupLayoutEntry {
+                    binding: 9,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: true },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                // References and the reference of every tile
+                wgpu::BindGroupLayoutEntry {
+                    binding: 10,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: Some(storage::References::min_size()),
+                    },
+                    count: None,
+                },
+                // Scale and reference offsets in f64, only read by the native kernel
+                wgpu::BindGroupLayoutEntry {
+                    binding: 11,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(reference::NATIVE_UNIFORM_SIZE),
                     },
                     count: None,
                 },
@@ -62,8 +180,40 @@ impl ComputePipeline {
             entry_point: "main",
         });
 
+        let subdivide_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Subdivide"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main_subdivide",
+        });
+
+        let clear_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Clear"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main_clear",
+        });
+
+        let detect_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Detect"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main_detect",
+        });
+
+        let refine_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Refine"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main_refine",
+        });
+
         Self {
             pipeline,
+            subdivide_pipeline,
+            clear_pipeline,
+            detect_pipeline,
+            refine_pipeline,
             bind_group_layout,
         }
     }
@@ -5,41 +5,359 @@ struct Globals {
     time: f32,
     scale: f32,
     radius: f32,
+    stripe_density: f32,
+    refine_threshold: f32,
+    projection: u32,
+    delta_precision: u32,
+    // Low part of the scale, see `Df64`
+    scale_lo: f32,
     center: vec2<f32>,
-    orbit_offset: vec2<f32>,
     coefficients: array<vec4<f32>, 4>,
 }
 
+// Orbits of every reference one after another
 struct OrbitBuffer {
-    iterations: u32,
+    length: u32,
     orbits: array<vec2<f32>>,
 }
 
+// Offset of the start of a reference from the center, in float-float
+struct Reference {
+    offset: vec2<f32>,
+    offset_lo: vec2<f32>,
+}
+
+// The reference of every tile is packed four to an element, see reference.rs. The count comes last
+// so the arrays start on the 16 byte boundaries uniforms need.
+struct References {
+    references: array<Reference, 16>,
+    map: array<vec4<u32>, 64>,
+    count: u32,
+}
+
+struct Trap {
+    kind: u32,
+    radius: f32,
+    position: vec2<f32>,
+    direction: vec2<f32>,
+}
+
+struct TrapBuffer {
+    count: u32,
+    traps: array<Trap>,
+}
+
+struct Channels {
+    iterations: f32,
+    trap_distance: f32,
+    trap_iteration: f32,
+    triangle_inequality: f32,
+    stripe: f32,
+    curvature: f32,
+    final_z: vec2<f32>,
+    distance: f32,
+    flags: u32,
+}
+
+// Must be kept in sync with the flags of `Channels` in storage.rs
+const FLAG_ESCAPED: u32 = 1u;
+const FLAG_FILLED: u32 = 2u;
+const FLAG_PENDING: u32 = 4u;
+const FLAG_GLITCHED: u32 = 8u;
+const FLAG_INTERIOR: u32 = 16u;
+
+// Samples whose full orbit comes this much closer to zero than the reference are glitched, after
+// Pauldelbrot. Must be kept in sync with `GLITCH_TOLERANCE` in engine/cpu.rs
+const GLITCH_TOLERANCE: f32 = 1e-3;
+
+struct ChannelBuffer {
+    channels: array<Channels>,
+}
+
+struct Tile {
+    origin: vec2<u32>,
+    // Size of the whole frame
+    dimensions: vec2<u32>,
+    pass_index: u32,
+    pass_count: u32,
+    // Sample plane of the channel buffer, see `sample_offsets`
+    plane: u32,
+}
+
+struct RefineList {
+    count: atomic<u32>,
+    pixels: array<u32>,
+}
+
+// ===================== Orbit traps ======================
+
+fn trap_distance(trap: Trap, z: vec2<f32>) -> f32 {
+    let offset = z - trap.position;
+    let along = abs(dot(offset, trap.direction));
+    let across = abs(offset.x * trap.direction.y - offset.y * trap.direction.x);
+    switch trap.kind {
+        // Line
+        case 1u: {
+            return across;
+        }
+        // Cross
+        case 2u: {
+            return min(across, along);
+        }
+        // Circle
+        case 3u: {
+            return abs(length(offset) - trap.radius);
+        }
+        // Point
+        default: {
+            return length(offset);
+        }
+    }
+}
+
+// ================= Statistical colouring ================
+
+struct Average {
+    sum: f32,
+    last: f32,
+    count: f32,
+}
+
+fn average_push(average: Average, t: f32) -> Average {
+    return Average(average.sum + t, t, average.count + 1.0);
+}
+
+// Interpolates between the averages with and without the final term by the
+// fractional part of the smooth iteration count
+fn average_smooth(average: Average, fraction: f32) -> f32 {
+    if (average.count < 2.0) {
+        return average.sum;
+    }
+
+    let current = average.sum / average.count;
+    let previous = (average.sum - average.last) / (average.count - 1.0);
+    return mix(previous, current, fraction);
+}
+
+// ============== Emulated double precision ================
+
+// Unevaluated sum of two f32 values, as `Df64` in df64.rs which every function here mirrors
+struct Df64 {
+    hi: f32,
+    lo: f32,
+}
+
+struct Df64Complex {
+    re: Df64,
+    im: Df64,
+}
+
+// Must be kept in sync with `Precision` in precision.rs
+const PRECISION_EMULATED: u32 = 1u;
+const PRECISION_NATIVE: u32 = 2u;
+
+const SPLITTER: f32 = 4097.0;
+
+fn two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let v = s - a;
+    let e = (a - (s - v)) + (b - v);
+    return Df64(s, e);
+}
+
+fn quick_two_sum(a: f32, b: f32) -> Df64 {
+    let s = a + b;
+    let e = b - (s - a);
+    return Df64(s, e);
+}
+
+fn split(a: f32) -> vec2<f32> {
+    let t = SPLITTER * a;
+    let hi = t - (t - a);
+    return vec2<f32>(hi, a - hi);
+}
+
+fn two_prod(a: f32, b: f32) -> Df64 {
+    let p = a * b;
+    let sa = split(a);
+    let sb = split(b);
+    let e = ((sa.x * sb.x - p) + sa.x * sb.y + sa.y * sb.x) + sa.y * sb.y;
+    return Df64(p, e);
+}
+
+fn df_add(a: Df64, b: Df64) -> Df64 {
+    let s = two_sum(a.hi, b.hi);
+    let t = two_sum(a.lo, b.lo);
+    let r = quick_two_sum(s.hi, s.lo + t.hi);
+    return quick_two_sum(r.hi, r.lo + t.lo);
+}
+
+fn df_sub(a: Df64, b: Df64) -> Df64 {
+    return df_add(a, Df64(-b.hi, -b.lo));
+}
+
+fn df_mul(a: Df64, b: Df64) -> Df64 {
+    let p = two_prod(a.hi, b.hi);
+    return quick_two_sum(p.hi, p.lo + (a.hi * b.lo + a.lo * b.hi));
+}
+
+fn dc_from(value: vec2<f32>) -> Df64Complex {
+    return Df64Complex(Df64(value.x, 0.0), Df64(value.y, 0.0));
+}
+
+fn dc_hi(value: Df64Complex) -> vec2<f32> {
+    return vec2<f32>(value.re.hi, value.im.hi);
+}
+
+fn dc_add(a: Df64Complex, b: Df64Complex) -> Df64Complex {
+    return Df64Complex(df_add(a.re, b.re), df_add(a.im, b.im));
+}
+
+fn dc_sub(a: Df64Complex, b: Df64Complex) -> Df64Complex {
+    return Df64Complex(df_sub(a.re, b.re), df_sub(a.im, b.im));
+}
+
+fn dc_mul(a: Df64Complex, b: Df64Complex) -> Df64Complex {
+    return Df64Complex(
+        df_sub(df_mul(a.re, b.re), df_mul(a.im, b.im)),
+        df_add(df_mul(a.re, b.im), df_mul(a.im, b.re))
+    );
+}
+
 // ================== Mandelbrot function =================
 
-fn mandelbrot(d0: vec2<f32>) -> f32 {
+// Takes the offset from the reference in float-float, of which only the leading parts are used
+// unless the perturbation step is done in double precision. The native steps live in
+// native_f64.wgsl, or native_stub.wgsl on devices without f64 support, see compute.rs, and build
+// their own offset from the one from the center of the view in units of the scale.
+fn mandelbrot(d0_df: Df64Complex, offset: vec2<f32>, reference: u32) -> Channels {
+    let iterations_max = orbit_iterations();
+    let first = reference * iterations_max;
     let a = globals.coefficients[0u].xy;
     let b = globals.coefficients[1u].xy;
     let c = globals.coefficients[2u].xy;
     let d = globals.coefficients[3u].xy;
 
-    var dn = cxmul(a, d0) + cxmul(b, cxpow(d0, 2.0)) + cxmul(c, cxpow(d0, 3.0)) + cxmul(d, cxpow(d0, 4.0));
+    let d0 = dc_hi(d0_df);
+    let higher_terms = cxmul(b, cxpow(d0, 2.0)) + cxmul(c, cxpow(d0, 3.0)) + cxmul(d, cxpow(d0, 4.0));
+    var dn = cxmul(a, d0) + higher_terms;
+    var dn_df = dc_add(dc_mul(dc_from(a), d0_df), dc_from(higher_terms));
+    if (globals.delta_precision == PRECISION_NATIVE) {
+        native_start(offset, reference, a, higher_terms);
+    }
     var xn = vec2<f32>(0.0, 0.0);
+    var trap_distance_min = 1e20;
+    var trap_iteration = 0u;
+
+    // Only the magnitude of c is needed, so single precision is plenty
+    let c_abs = length(globals.center + references.references[reference].offset + d0);
+    var z = vec2<f32>(0.0, 0.0);
+    var z_prev = vec2<f32>(0.0, 0.0);
+    var z_prev2 = vec2<f32>(0.0, 0.0);
+    var triangle_inequality = Average(0.0, 0.0, 0.0);
+    var stripe = Average(0.0, 0.0, 0.0);
+    var curvature = Average(0.0, 0.0, 0.0);
+    var escaped = false;
+    var glitched = false;
+
+    // Derivative of the full orbit with respect to c, for the distance estimate. The orbit starts
+    // at z_1 = c, whose derivative is 1.
+    var derivative = vec2<f32>(1.0, 0.0);
+
+    // Derivative with respect to z_1, which shrinks towards zero on orbits attracted to a cycle
+    var multiplier = vec2<f32>(1.0, 0.0);
 
     var i = 0u;
-    for (; i < orbit_buffer.iterations; i += 1u) {
-        xn = orbit_buffer.orbits[i];
-        dn = cxmul(2.0 * xn + dn, dn) + d0;
-        if (length(dn) > globals.radius) {
+    for (; i < iterations_max; i += 1u) {
+        xn = orbit_buffer.orbits[first + i];
+
+        // Traps and statistics are measured against the full orbit value Z_n + dz_n
+        z = xn + dn;
+        for (var t = 0u; t < trap_buffer.count; t += 1u) {
+            let distance = trap_distance(trap_buffer.traps[t], z);
+            if (distance < trap_distance_min) {
+                trap_distance_min = distance;
+                trap_iteration = i;
+            }
+        }
+
+        stripe = average_push(stripe, 0.5 * sin(globals.stripe_density * atan2(z.y, z.x)) + 0.5);
+
+        if (i > 0u) {
+            let z_prev_sqr = dot(z_prev, z_prev);
+            let lower = abs(z_prev_sqr - c_abs);
+            let upper = z_prev_sqr + c_abs;
+            if (upper - lower > 0.0) {
+                triangle_inequality = average_push(triangle_inequality, (length(z) - lower) / (upper - lower));
+            }
+        }
+
+        if (i > 1u && any(z_prev != z_prev2)) {
+            let turn = cxdiv(z - z_prev, z_prev - z_prev2);
+            curvature = average_push(curvature, abs(atan2(turn.y, turn.x)) / PI);
+        }
+
+        // The escape radius bounds the orbit itself, the delta alone is off by the reference value
+        if (length(z) > globals.radius) {
+            escaped = true;
+            break;
+        }
+
+        // Past its escape the reference repeats its last point, which no sample can follow
+        if (length(xn) > globals.radius) {
+            glitched = true;
             break;
         }
+
+        glitched = glitched || length(z) < GLITCH_TOLERANCE * length(xn);
+
+        z_prev2 = z_prev;
+        z_prev = z;
+        derivative = 2.0 * cxmul(z, derivative) + vec2<f32>(1.0, 0.0);
+        multiplier = 2.0 * cxmul(z, multiplier);
+        if (globals.delta_precision == PRECISION_EMULATED) {
+            dn_df = dc_add(dc_mul(dc_add(dc_from(2.0 * xn), dn_df), dn_df), d0_df);
+            dn = dc_hi(dn_df);
+        } else if (globals.delta_precision == PRECISION_NATIVE) {
+            dn = native_step(first + i);
+        } else {
+            dn = cxmul(2.0 * xn + dn, dn) + d0;
+        }
     }
 
-    return f32(i) / f32(orbit_buffer.iterations);
+    var iterations = f32(i);
+    var fraction = 1.0;
+    var distance = 0.0;
+    if (escaped) {
+        let overshoot = log2(log(length(z)) / log(globals.radius));
+        fraction = clamp(1.0 - overshoot, 0.0, 1.0);
+        iterations += fraction;
+        distance = length(z) * log(length(z)) / length(derivative);
+    }
+
+    var channels: Channels;
+    channels.iterations = iterations / f32(iterations_max);
+    channels.trap_distance = trap_distance_min;
+    channels.trap_iteration = f32(trap_iteration) / f32(iterations_max);
+    channels.triangle_inequality = average_smooth(triangle_inequality, fraction);
+    channels.stripe = average_smooth(stripe, fraction);
+    channels.curvature = average_smooth(curvature, fraction);
+    channels.final_z = z;
+    channels.distance = distance;
+    channels.flags = select(0u, FLAG_ESCAPED, escaped);
+    if (!escaped && length(multiplier) < 1.0) {
+        channels.flags |= FLAG_INTERIOR;
+    }
+    if (glitched) {
+        channels.flags |= FLAG_GLITCHED;
+    }
+    return channels;
 }
 
 // ================ Complex Math functions ================
 
+const PI: f32 = 3.14159265358979;
+
 fn cxmul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
     return vec2<f32>(
         a.x * b.x - a.y * b.y,
@@ -47,6 +365,10 @@ fn cxmul(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
     );
 }
 
+fn cxdiv(a: vec2<f32>, b: vec2<f32>) -> vec2<f32> {
+    return cxmul(a, vec2<f32>(b.x, -b.y)) / dot(b, b);
+}
+
 fn cxpow(a: vec2<f32>, b: f32) -> vec2<f32> {
     let r = length(a);
     let theta = atan2(a.y, a.x);
@@ -67,23 +389,327 @@ var<uniform> globals: Globals;
 var<storage, read> orbit_buffer: OrbitBuffer;
 
 @group(0) @binding(2)
-var tex: texture_storage_2d<rgba32float, read_write>;
+var<storage, read> trap_buffer: TrapBuffer;
+
+@group(0) @binding(3)
+var<storage, read_write> channel_buffer: ChannelBuffer;
 
+@group(0) @binding(4)
+var<uniform> tile: Tile;
+
+// Sub-pixel position of the sample in every plane, in pixels from the corner
+@group(0) @binding(5)
+var<storage, read> sample_offsets: array<vec2<f32>>;
+
+// Pixels found by `main_detect`, the refinement map and the refined samples, see refinement.rs
+@group(0) @binding(6)
+var<storage, read_write> refine_list: RefineList;
+
+@group(0) @binding(7)
+var<storage, read_write> refinement_map: array<u32>;
+
+@group(0) @binding(8)
+var<storage, read_write> refined_buffer: ChannelBuffer;
+
+// Bindings 9 and 11 are the f64 orbit buffer and globals of native_f64.wgsl
+@group(0) @binding(10)
+var<uniform> references: References;
+
+// Must be kept in sync with `TILES` in reference.rs
+const REFERENCE_TILES: u32 = 16u;
+
+// Length of the orbit of every reference
+fn orbit_iterations() -> u32 {
+    return orbit_buffer.length / references.count;
+}
+
+// Reference of the tile a position in pixels falls into
+fn reference_of(position: vec2<f32>, dimensions: vec2<u32>) -> u32 {
+    let last = f32(REFERENCE_TILES - 1u);
+    let cell = clamp(position * f32(REFERENCE_TILES) / vec2<f32>(dimensions), vec2<f32>(0.0), vec2<f32>(last));
+    let tile = u32(cell.y) * REFERENCE_TILES + u32(cell.x);
+    return references.map[tile / 4u][tile % 4u];
+}
+
+// Columns sweep the circle and rows step down in log radius, in units of the scale. Must be kept
+// in sync with `offset` in exponential.rs
+fn exponential_offset(position: vec2<f32>, dimensions: vec2<u32>) -> vec2<f32> {
+    let width = f32(dimensions.x);
+    let angle = 2.0 * PI * position.x / width;
+    let radius = exp(2.0 * PI * (position.y - f32(dimensions.y)) / width);
+    return radius * vec2<f32>(cos(angle), sin(angle));
+}
+
+// Evaluates a position in pixels from the corner of the frame
+fn sample_at(position: vec2<f32>, dimensions: vec2<u32>) -> Channels {
+    var offset: vec2<f32>;
+    if (globals.projection == 1u) {
+        offset = exponential_offset(position, dimensions);
+    } else {
+        let aspect_ratio = vec2<f32>(f32(dimensions.x) / f32(dimensions.y), 1.0);
+        let uv = position / vec2<f32>(dimensions);
+        offset = aspect_ratio * (uv - 0.5);
+    }
+
+    let reference = reference_of(position, dimensions);
+    let start = references.references[reference];
+    let scale = Df64(globals.scale, globals.scale_lo);
+    let orbit_offset = Df64Complex(
+        Df64(start.offset.x, start.offset_lo.x),
+        Df64(start.offset.y, start.offset_lo.y)
+    );
+
+    let x = Df64Complex(df_mul(scale, Df64(offset.x, 0.0)), df_mul(scale, Df64(offset.y, 0.0)));
+    return mandelbrot(dc_sub(x, orbit_offset), offset, reference);
+}
+
+fn sample(pixel: vec2<u32>, dimensions: vec2<u32>) -> Channels {
+    return sample_at(vec2<f32>(pixel) + sample_offsets[tile.plane], dimensions);
+}
+
+fn store(pixel: vec2<u32>, dimensions: vec2<u32>, channels: Channels, filled: bool) {
+    var stored = channels;
+    if (filled) {
+        stored.flags |= FLAG_FILLED;
+    }
+
+    // Planes of samples are stored one after another, each a full frame
+    let index = (tile.plane * dimensions.y + pixel.y) * dimensions.x + pixel.x;
+    channel_buffer.channels[index] = stored;
+}
+
+// Marks every pixel of every plane, laid out along z, as not rendered yet. Runs once before the
+// first tile of a view, so the window can tell fresh pixels from ones it has to make up.
+@compute
+@workgroup_size(8, 8, 1)
+fn main_clear(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>
+) {
+    let dimensions = tile.dimensions;
+    let pixel = g_invocation_id.xy;
+    if (any(pixel >= dimensions)) {
+        return;
+    }
+
+    var channels: Channels;
+    channels.flags = FLAG_PENDING;
+    let index = (g_invocation_id.z * dimensions.y + pixel.y) * dimensions.x + pixel.x;
+    channel_buffer.channels[index] = channels;
+}
+
+// Every pass evaluates pixels on a grid twice as fine as the previous one and fills the block up
+// to the next grid point, so the image sharpens without any pixel being evaluated twice. Pixels
+// on the coarser grid are skipped since earlier passes have already evaluated them.
+// Workgroups are 8x8, which must be kept in sync with `WORKGROUP_SIZE` in engine/gpu.rs
 @compute
-@workgroup_size(1, 1, 1)
+@workgroup_size(8, 8, 1)
 fn main(
     @builtin(global_invocation_id) g_invocation_id: vec3<u32>
 ) {
-    let dimensions = textureDimensions(tex);
-    let aspect_ratio = vec2<f32>(f32(dimensions.x) / f32(dimensions.y), 1.0);
-    
-    let uv = vec2<f32>(
-        f32(g_invocation_id.x) / f32(dimensions.x),
-        f32(g_invocation_id.y) / f32(dimensions.y)
+    let dimensions = tile.dimensions;
+    let block = 1u << (tile.pass_count - 1u - tile.pass_index);
+    if (tile.pass_index > 0u && all(g_invocation_id.xy % 2u == vec2<u32>(0u))) {
+        return;
+    }
+
+    let pixel = tile.origin + g_invocation_id.xy * block;
+    if (any(pixel >= dimensions)) {
+        return;
+    }
+
+    let channels = sample(pixel, dimensions);
+    let end = min(pixel + block, dimensions);
+    for (var y = pixel.y; y < end.y; y += 1u) {
+        for (var x = pixel.x; x < end.x; x += 1u) {
+            store(vec2<u32>(x, y), dimensions, channels, false);
+        }
+    }
+}
+
+// ============ Mariani-Silver subdivision ================
+
+// Must be kept in sync with `TILE_SIZE` in subdivision.rs
+const SUBDIVISION_TILE_SIZE: u32 = 16u;
+const SUBDIVISION_MIN_SIZE: u32 = 4u;
+
+fn escape_key(channels: Channels) -> u32 {
+    return u32(channels.iterations * f32(orbit_iterations()));
+}
+
+// Rectangles are packed as (x, y, width, height)
+fn border_length(rect: vec4<u32>) -> u32 {
+    if (rect.w == 1u) {
+        return rect.z;
+    }
+
+    return 2u * rect.z + 2u * (rect.w - 2u);
+}
+
+fn border_pixel(rect: vec4<u32>, i: u32) -> vec2<u32> {
+    if (i < rect.z) {
+        return vec2<u32>(rect.x + i, rect.y);
+    } else if (i < 2u * rect.z) {
+        return vec2<u32>(rect.x + i - rect.z, rect.y + rect.w - 1u);
+    }
+
+    let j = i - 2u * rect.z;
+    return vec2<u32>(rect.x + (j % 2u) * (rect.z - 1u), rect.y + 1u + j / 2u);
+}
+
+// Each invocation renders one tile: the border of a rectangle is evaluated first, and the
+// rectangle is filled when the whole border escapes on the same iteration. Otherwise it is split
+// into quadrants, down to a minimum size where every pixel is evaluated.
+@compute
+@workgroup_size(8, 8, 1)
+fn main_subdivide(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>
+) {
+    let dimensions = tile.dimensions;
+    let origin = tile.origin + g_invocation_id.xy * SUBDIVISION_TILE_SIZE;
+    if (any(origin >= dimensions)) {
+        return;
+    }
+
+    var stack: array<vec4<u32>, 16>;
+    var top = 1u;
+    stack[0] = vec4<u32>(origin, min(vec2<u32>(SUBDIVISION_TILE_SIZE), dimensions - origin));
+
+    while (top > 0u) {
+        top -= 1u;
+        let rect = stack[top];
+
+        let first = sample(rect.xy, dimensions);
+        let key = escape_key(first);
+        var is_uniform = true;
+        store(rect.xy, dimensions, first, false);
+        for (var i = 1u; i < border_length(rect); i += 1u) {
+            let pixel = border_pixel(rect, i);
+            let channels = sample(pixel, dimensions);
+            is_uniform = is_uniform && escape_key(channels) == key;
+            store(pixel, dimensions, channels, false);
+        }
+
+        if (rect.z <= 2u || rect.w <= 2u) {
+            continue;
+        }
+
+        if (is_uniform || rect.z <= SUBDIVISION_MIN_SIZE || rect.w <= SUBDIVISION_MIN_SIZE) {
+            for (var y = rect.y + 1u; y < rect.y + rect.w - 1u; y += 1u) {
+                for (var x = rect.x + 1u; x < rect.x + rect.z - 1u; x += 1u) {
+                    let pixel = vec2<u32>(x, y);
+                    if (is_uniform) {
+                        store(pixel, dimensions, first, true);
+                    } else {
+                        store(pixel, dimensions, sample(pixel, dimensions), false);
+                    }
+                }
+            }
+
+            continue;
+        }
+
+        // Quadrants share their inner edges, matching `Rect::split` in subdivision.rs
+        let size0 = rect.zw / 2u + 1u;
+        let size1 = rect.zw - size0 + 1u;
+        let middle = rect.xy + size0 - 1u;
+        stack[top] = vec4<u32>(rect.x, rect.y, size0.x, size0.y);
+        stack[top + 1u] = vec4<u32>(middle.x, rect.y, size1.x, size0.y);
+        stack[top + 2u] = vec4<u32>(rect.x, middle.y, size0.x, size1.y);
+        stack[top + 3u] = vec4<u32>(middle.x, middle.y, size1.x, size1.y);
+        top += 4u;
+    }
+}
+
+// ================ Adaptive refinement ===================
+
+// Must be kept in sync with `GRID_SIZE` in refinement.rs
+const REFINE_GRID_SIZE: u32 = 3u;
+const REFINE_SAMPLES: u32 = 9u;
+
+fn refine_value(channels: Channels) -> vec2<f32> {
+    return vec2<f32>(
+        channels.iterations * f32(orbit_iterations()),
+        log2(max(channels.distance, 1e-30))
     );
+}
+
+// Whether the 3x3 neighbourhood of a pixel is mixed between escaped and interior pixels, or
+// varies by more than the threshold. Must be kept in sync with `needs_refinement` in
+// refinement.rs
+fn needs_refinement(pixel: vec2<u32>, dimensions: vec2<u32>) -> bool {
+    // Values are taken relative to the center to keep the variance precise
+    let center = refine_value(channel_buffer.channels[pixel.y * dimensions.x + pixel.x]);
+    let start = max(pixel, vec2<u32>(1u)) - 1u;
+    let end = min(pixel + 2u, dimensions);
+    var count = 0u;
+    var escaped = 0u;
+    var sum = vec2<f32>(0.0);
+    var sum_sqr = vec2<f32>(0.0);
+    for (var y = start.y; y < end.y; y += 1u) {
+        for (var x = start.x; x < end.x; x += 1u) {
+            let neighbour = channel_buffer.channels[y * dimensions.x + x];
+            count += 1u;
+            if ((neighbour.flags & FLAG_ESCAPED) != 0u) {
+                escaped += 1u;
+                let v = refine_value(neighbour) - center;
+                sum += v;
+                sum_sqr += v * v;
+            }
+        }
+    }
+
+    if (escaped == 0u) {
+        return false;
+    }
+
+    if (escaped < count) {
+        return true;
+    }
 
-    let x = globals.scale * aspect_ratio * (uv - 0.5) - globals.orbit_offset;
-    let color = vec3<f32>(mandelbrot(x));
+    let mean = sum / f32(count);
+    let variance = max(sum_sqr / f32(count) - mean * mean, vec2<f32>(0.0));
+    return any(variance > vec2<f32>(globals.refine_threshold * globals.refine_threshold));
+}
+
+// Looks for pixels to refine in the first plane of the finished frame. Pixels beyond the capacity
+// of the list are left alone.
+@compute
+@workgroup_size(8, 8, 1)
+fn main_detect(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>
+) {
+    let dimensions = tile.dimensions;
+    let pixel = tile.origin + g_invocation_id.xy;
+    if (any(pixel >= dimensions) || !needs_refinement(pixel, dimensions)) {
+        return;
+    }
+
+    let slot = atomicAdd(&refine_list.count, 1u);
+    if (slot < arrayLength(&refine_list.pixels)) {
+        let index = pixel.y * dimensions.x + pixel.x;
+        refine_list.pixels[slot] = index;
+        refinement_map[index] = slot + 1u;
+    }
+}
+
+// Every invocation renders one extra sample of one refined pixel, on a regular grid within it
+@compute
+@workgroup_size(64, 1, 1)
+fn main_refine(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>
+) {
+    let item = tile.origin.x + g_invocation_id.x;
+    let slot = item / REFINE_SAMPLES;
+    let count = min(atomicLoad(&refine_list.count), arrayLength(&refine_list.pixels));
+    if (slot >= count) {
+        return;
+    }
 
-    textureStore(tex, g_invocation_id.xy, vec4<f32>(color, 1.0));
+    let dimensions = tile.dimensions;
+    let index = refine_list.pixels[slot];
+    let pixel = vec2<u32>(index % dimensions.x, index / dimensions.x);
+    let sample = item % REFINE_SAMPLES;
+    let cell = vec2<f32>(f32(sample % REFINE_GRID_SIZE), f32(sample / REFINE_GRID_SIZE));
+    let offset = (cell + 0.5) / f32(REFINE_GRID_SIZE);
+    refined_buffer.channels[item] = sample_at(vec2<f32>(pixel) + offset, dimensions);
 }
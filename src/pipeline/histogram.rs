@@ -0,0 +1,66 @@
+pub struct HistogramPipeline {
+    pub accumulate_pipeline: wgpu::ComputePipeline,
+    pub cumulate_pipeline: wgpu::ComputePipeline,
+    pub bind_group_layout: wgpu::BindGroupLayout,
+}
+
+impl HistogramPipeline {
+    pub fn new(device: &wgpu::Device) -> Self {
+        let module = {
+            let descriptor = wgpu::include_wgsl!("histogram.wgsl");
+            device.create_shader_module(descriptor)
+        };
+
+        let storage_entry = |binding, read_only| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage { read_only },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("Histogram"),
+            entries: &[
+                // Input channels
+                storage_entry(0, true),
+                // Bin counts
+                storage_entry(1, false),
+                // Cumulative distribution
+                storage_entry(2, false),
+                // Frame statistics
+                storage_entry(3, false),
+            ],
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Histogram"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+
+        let accumulate_pipeline =
+            device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some("Histogram accumulate"),
+                layout: Some(&pipeline_layout),
+                module: &module,
+                entry_point: "accumulate",
+            });
+
+        let cumulate_pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Histogram cumulate"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "cumulate",
+        });
+
+        Self {
+            accumulate_pipeline,
+            cumulate_pipeline,
+            bind_group_layout,
+        }
+    }
+}
@@ -0,0 +1,171 @@
+
+// ======================== Structs =======================
+
+// Must be kept in sync with `Channels` in compute.wgsl
+struct Channels {
+    iterations: f32,
+    trap_distance: f32,
+    trap_iteration: f32,
+    triangle_inequality: f32,
+    stripe: f32,
+    curvature: f32,
+    final_z: vec2<f32>,
+    distance: f32,
+    flags: u32,
+}
+
+const FLAG_ESCAPED: u32 = 1u;
+const FLAG_FILLED: u32 = 2u;
+const FLAG_PENDING: u32 = 4u;
+const FLAG_GLITCHED: u32 = 8u;
+const FLAG_INTERIOR: u32 = 16u;
+
+// Must be kept in sync with `BINS` in histogram.rs, and equal to the workgroup size
+const BINS: u32 = 256u;
+
+struct ChannelBuffer {
+    channels: array<Channels>,
+}
+
+// Must be kept in sync with the words of `Statistics` in statistics.rs
+struct Statistics {
+    // Length of the orbits, written before the pass
+    iterations: u32,
+    // Escaped, interior, max iteration and glitched samples
+    counts: array<atomic<u32>, 4>,
+    min_escape: atomic<u32>,
+    max_escape: atomic<u32>,
+    // Iterations of the filled in samples, as a low and a high word
+    skipped_lo: atomic<u32>,
+    skipped_hi: atomic<u32>,
+}
+
+// ========================= Main =========================
+
+@group(0) @binding(0)
+var<storage, read> channel_buffer: ChannelBuffer;
+
+@group(0) @binding(1)
+var<storage, read_write> histogram: array<atomic<u32>, BINS>;
+
+@group(0) @binding(2)
+var<storage, read_write> cdf: array<f32, BINS>;
+
+@group(0) @binding(3)
+var<storage, read_write> statistics: Statistics;
+
+var<workgroup> local_histogram: array<atomic<u32>, BINS>;
+var<workgroup> sums: array<u32, BINS>;
+var<workgroup> local_counts: array<atomic<u32>, 4>;
+var<workgroup> local_min_escape: atomic<u32>;
+var<workgroup> local_max_escape: atomic<u32>;
+
+// Every workgroup counts a strided share of the pixels in its own memory first, so only one
+// global atomic per bin and workgroup is needed. Statistics are reduced the same way, from the
+// counts of every invocation.
+@compute
+@workgroup_size(256, 1, 1)
+fn accumulate(
+    @builtin(global_invocation_id) g_invocation_id: vec3<u32>,
+    @builtin(local_invocation_index) local_index: u32,
+    @builtin(num_workgroups) workgroups: vec3<u32>,
+) {
+    if (local_index == 0u) {
+        atomicStore(&local_min_escape, 0xffffffffu);
+    }
+
+    workgroupBarrier();
+
+    var counts = array<u32, 4>(0u, 0u, 0u, 0u);
+    var min_escape = 0xffffffffu;
+    var max_escape = 0u;
+    var skipped_lo = 0u;
+    var skipped_hi = 0u;
+
+    let count = arrayLength(&channel_buffer.channels);
+    let stride = workgroups.x * BINS;
+    for (var i = g_invocation_id.x; i < count; i += stride) {
+        let channels = channel_buffer.channels[i];
+        if ((channels.flags & FLAG_PENDING) != 0u) {
+            continue;
+        }
+
+        let escape = u32(channels.iterations * f32(statistics.iterations));
+        if ((channels.flags & FLAG_ESCAPED) != 0u) {
+            let bin = min(u32(channels.iterations * f32(BINS)), BINS - 1u);
+            atomicAdd(&local_histogram[bin], 1u);
+            counts[0] += 1u;
+            min_escape = min(min_escape, escape);
+            max_escape = max(max_escape, escape);
+        } else if ((channels.flags & FLAG_INTERIOR) != 0u) {
+            counts[1] += 1u;
+        } else {
+            counts[2] += 1u;
+        }
+
+        if ((channels.flags & FLAG_GLITCHED) != 0u) {
+            counts[3] += 1u;
+        }
+
+        if ((channels.flags & FLAG_FILLED) != 0u) {
+            skipped_hi += select(0u, 1u, skipped_lo + escape < skipped_lo);
+            skipped_lo += escape;
+        }
+    }
+
+    for (var c = 0u; c < 4u; c += 1u) {
+        if (counts[c] > 0u) {
+            atomicAdd(&local_counts[c], counts[c]);
+        }
+    }
+
+    atomicMin(&local_min_escape, min_escape);
+    atomicMax(&local_max_escape, max_escape);
+
+    // Sums of iterations can outgrow a word, so the carry of the low word goes into the high one
+    if (skipped_lo > 0u || skipped_hi > 0u) {
+        let previous = atomicAdd(&statistics.skipped_lo, skipped_lo);
+        skipped_hi += select(0u, 1u, previous + skipped_lo < previous);
+        atomicAdd(&statistics.skipped_hi, skipped_hi);
+    }
+
+    workgroupBarrier();
+
+    let local_count = atomicLoad(&local_histogram[local_index]);
+    if (local_count > 0u) {
+        atomicAdd(&histogram[local_index], local_count);
+    }
+
+    if (local_index < 4u) {
+        atomicAdd(&statistics.counts[local_index], atomicLoad(&local_counts[local_index]));
+    }
+
+    if (local_index == 0u) {
+        atomicMin(&statistics.min_escape, atomicLoad(&local_min_escape));
+        atomicMax(&statistics.max_escape, atomicLoad(&local_max_escape));
+    }
+}
+
+// Inclusive prefix sum over the bins in a single workgroup, normalized by the escaped pixels
+@compute
+@workgroup_size(256, 1, 1)
+fn cumulate(
+    @builtin(local_invocation_index) local_index: u32,
+) {
+    sums[local_index] = atomicLoad(&histogram[local_index]);
+    workgroupBarrier();
+
+    for (var offset = 1u; offset < BINS; offset *= 2u) {
+        var previous = 0u;
+        if (local_index >= offset) {
+            previous = sums[local_index - offset];
+        }
+
+        workgroupBarrier();
+        sums[local_index] += previous;
+        workgroupBarrier();
+    }
+
+    let total = max(sums[BINS - 1u], 1u);
+    cdf[local_index] = f32(sums[local_index]) / f32(total);
+}
@@ -0,0 +1,38 @@
+// ============== Native double precision =================
+
+// Appended to compute.wgsl on devices that support f64 in shaders. The delta is kept here between
+// steps and only its f32 rounding is handed back to `mandelbrot`.
+
+@group(0) @binding(9)
+var<storage, read> orbit_f64: array<vec2<f64>>;
+
+// The delta is built from these rather than the float-float offset, whose parts run out of exponent
+// range long before f64 does. Must be kept in sync with `native_uniform` in reference.rs.
+struct NativeGlobals {
+    scale: f64,
+    offsets: array<vec2<f64>, 16>,
+}
+
+@group(0) @binding(11)
+var<uniform> native_globals: NativeGlobals;
+
+var<private> native_d0: vec2<f64>;
+var<private> native_dn: vec2<f64>;
+
+fn cxmul_f64(a: vec2<f64>, b: vec2<f64>) -> vec2<f64> {
+    return vec2<f64>(
+        a.x * b.x - a.y * b.y,
+        a.x * b.y + a.y * b.x
+    );
+}
+
+fn native_start(offset: vec2<f32>, reference: u32, a: vec2<f32>, higher_terms: vec2<f32>) {
+    native_d0 = native_globals.scale * vec2<f64>(offset) - native_globals.offsets[reference];
+    native_dn = cxmul_f64(vec2<f64>(a), native_d0) + vec2<f64>(higher_terms);
+}
+
+fn native_step(i: u32) -> vec2<f32> {
+    let xn = orbit_f64[i];
+    native_dn = cxmul_f64(xn + xn + native_dn, native_dn) + native_d0;
+    return vec2<f32>(native_dn);
+}
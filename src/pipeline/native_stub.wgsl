@@ -0,0 +1,9 @@
+// ============== Native double precision =================
+
+// Appended to compute.wgsl on devices without f64 support, where the native path is never chosen
+
+fn native_start(offset: vec2<f32>, reference: u32, a: vec2<f32>, higher_terms: vec2<f32>) {}
+
+fn native_step(i: u32) -> vec2<f32> {
+    return vec2<f32>(0.0);
+}
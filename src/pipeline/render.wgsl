@@ -16,17 +16,85 @@ fn vs_main(in: VertexInput) -> VertexOutput {
     return out;
 }
 
+// Maps a position in uv coordinates onto the placeholder, see `Reprojection` in storage.rs
+struct Reprojection {
+    scale: vec2<f32>,
+    offset: vec2<f32>,
+}
+
 @group(0) @binding(0)
 var tex: texture_storage_2d<rgba32float, read>;
 
+// The last complete image, drawn wherever the current one has pixels left to render
+@group(0) @binding(1)
+var placeholder: texture_storage_2d<rgba32float, read>;
+
+@group(0) @binding(2)
+var<uniform> reprojection: Reprojection;
+
+// Must be kept in sync with `MAX_RENDER_SCALE` in app.rs
+const MAX_FOOTPRINT: i32 = 4;
+
+fn load(image: texture_storage_2d<rgba32float, read>, texel: vec2<i32>) -> vec4<f32> {
+    let dimensions = vec2<i32>(textureDimensions(image));
+    return textureLoad(image, clamp(texel, vec2<i32>(0), dimensions - 1));
+}
+
+// Interpolates between the four texels surrounding a position in texel space
+fn bilinear(image: texture_storage_2d<rgba32float, read>, position: vec2<f32>) -> vec4<f32> {
+    let base = floor(position);
+    let t = position - base;
+    let texel = vec2<i32>(base);
+    let bottom = mix(load(image, texel), load(image, texel + vec2<i32>(1, 0)), t.x);
+    let top = mix(load(image, texel + vec2<i32>(0, 1)), load(image, texel + vec2<i32>(1, 1)), t.x);
+    return mix(bottom, top, t.y);
+}
+
+// Averages every texel covered by a fragment when the texture is larger than the surface
+fn box_filter(center: vec2<f32>, footprint: vec2<f32>) -> vec4<f32> {
+    let origin = vec2<i32>(floor(center - 0.5 * footprint));
+    let count = clamp(vec2<i32>(round(footprint)), vec2<i32>(1), vec2<i32>(MAX_FOOTPRINT));
+
+    var sum = vec4<f32>(0.0);
+    for (var y = 0; y < count.y; y += 1) {
+        for (var x = 0; x < count.x; x += 1) {
+            sum += load(tex, origin + vec2<i32>(x, y));
+        }
+    }
+
+    return sum / f32(count.x * count.y);
+}
+
+// The placeholder moved and scaled to where it lies in the current view, transparent beyond its
+// edges
+fn reprojected(uv: vec2<f32>) -> vec4<f32> {
+    let position = uv * reprojection.scale + reprojection.offset;
+    if (any(position < vec2<f32>(0.0)) || any(position > vec2<f32>(1.0))) {
+        return vec4<f32>(0.0);
+    }
+
+    let dimensions = vec2<f32>(textureDimensions(placeholder));
+    return bilinear(placeholder, position * dimensions - 0.5);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let dimension = textureDimensions(tex);
-    let tex_coords = vec2<u32>(
-        u32(in.uv.x * f32(dimension.x)),
-        u32(in.uv.y * f32(dimension.y)),
-    );
-
-    let color = textureLoad(tex, tex_coords);
-    return color;
+    let dimensions = vec2<f32>(textureDimensions(tex));
+    let position = in.uv * dimensions;
+
+    // Number of texels covered by this fragment along each axis
+    let footprint = fwidth(in.uv) * dimensions;
+    var color: vec4<f32>;
+    if (any(footprint > vec2<f32>(1.0))) {
+        color = box_filter(position, footprint);
+    } else {
+        color = bilinear(tex, position - 0.5);
+    }
+
+    // Pending pixels are transparent, with the placeholder showing through
+    if (color.a < 1.0) {
+        color += (1.0 - color.a) * reprojected(in.uv);
+    }
+
+    return vec4<f32>(color.rgb, 1.0);
 }
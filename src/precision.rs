@@ -1 +1,23 @@
 pub const PRECISION: u32 = 128;
+
+// Arithmetic of the perturbation step in the compute kernels
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Precision {
+    Single,
+    // Float-float emulation, see df64.rs
+    Emulated,
+    // Only available in the GPU engine, on devices that support f64 in shaders
+    Native,
+}
+
+impl Precision {
+    pub const ALL: [Precision; 3] = [Precision::Single, Precision::Emulated, Precision::Native];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Precision::Single => "Single (f32)",
+            Precision::Emulated => "Emulated double (df64)",
+            Precision::Native => "Native double (f64)",
+        }
+    }
+}
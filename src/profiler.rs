@@ -0,0 +1,324 @@
+use std::{
+    cell::Cell,
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+/*
+    GPU timestamps written around every pass of a frame, read back without blocking a few frames
+    later. Only one frame is measured at a time, the ones rendered while its results are in flight
+    go unmeasured. Every stage has a pair of queries, and stages that did not run in a frame are
+    left out of the readback, as resolving unwritten queries is not allowed.
+*/
+
+// Frames kept in the rolling history
+pub const HISTORY: usize = 120;
+
+// Resolved stages are spaced out, as every resolve has to start on this alignment
+const RESOLVE_STRIDE: u64 = wgpu::QUERY_RESOLVE_BUFFER_ALIGNMENT;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stage {
+    // Copying the view to the GPU engine
+    Upload,
+    // Every tile the GPU engine rendered during the frame
+    Compute,
+    Histogram,
+    Colouring,
+    Render,
+    Gui,
+}
+
+// Durations of one frame in milliseconds, for every stage that ran in it
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameTimes {
+    pub stages: [Option<f32>; Stage::ALL.len()],
+    // Time spent on the CPU computing the reference orbits, when they changed
+    pub orbit: Option<f32>,
+}
+
+pub struct Profiler {
+    // Missing when the device does not support timestamp queries
+    queries: Option<Queries>,
+    // Nanoseconds per timestamp tick
+    period: f32,
+    // Whether the current frame is measured, and which stages it wrote both queries of
+    recording: bool,
+    written: Cell<u32>,
+    frame: FrameTimes,
+    // Stages written in the frame being read back and its CPU timings
+    pending: (u32, FrameTimes),
+    // Set by the map callback while a readback is in flight
+    readback: Option<Arc<AtomicBool>>,
+}
+
+struct Queries {
+    set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 6] = [
+        Stage::Upload,
+        Stage::Compute,
+        Stage::Histogram,
+        Stage::Colouring,
+        Stage::Render,
+        Stage::Gui,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stage::Upload => "Upload",
+            Stage::Compute => "Compute",
+            Stage::Histogram => "Histogram",
+            Stage::Colouring => "Colouring",
+            Stage::Render => "Render",
+            Stage::Gui => "GUI",
+        }
+    }
+
+    fn bit(&self) -> u32 {
+        1 << *self as u32
+    }
+}
+
+impl FrameTimes {
+    // Sum of every stage on the GPU
+    pub fn gpu(&self) -> f32 {
+        self.stages.iter().flatten().sum()
+    }
+}
+
+impl Profiler {
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let queries = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| {
+                let size = RESOLVE_STRIDE * Stage::ALL.len() as u64;
+                let buffer = |label, usage| {
+                    device.create_buffer(&wgpu::BufferDescriptor {
+                        label: Some(label),
+                        size,
+                        usage,
+                        mapped_at_creation: false,
+                    })
+                };
+
+                Queries {
+                    set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                        label: Some("Timestamp queries"),
+                        ty: wgpu::QueryType::Timestamp,
+                        count: 2 * Stage::ALL.len() as u32,
+                    }),
+                    resolve_buffer: buffer(
+                        "Timestamp resolve buffer",
+                        wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                    ),
+                    readback_buffer: buffer(
+                        "Timestamp readback buffer",
+                        wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                    ),
+                }
+            });
+
+        Self {
+            queries,
+            period: queue.get_timestamp_period(),
+            recording: false,
+            written: Cell::new(0),
+            frame: FrameTimes::default(),
+            pending: (0, FrameTimes::default()),
+            readback: None,
+        }
+    }
+
+    // Measures the next frame unless the previous measurement is still in flight
+    pub fn begin_frame(&mut self) {
+        self.recording = self.readback.is_none();
+        self.written.set(0);
+        self.frame = FrameTimes::default();
+    }
+
+    pub fn set_orbit_time(&mut self, milliseconds: f32) {
+        self.frame.orbit = Some(milliseconds);
+    }
+
+    // Writes the timestamp at the start or the end of a stage, outside of any pass
+    pub fn stamp(&self, encoder: &mut wgpu::CommandEncoder, stage: Stage, end: bool) {
+        let Some(queries) = self.queries.as_ref().filter(|_| self.recording) else {
+            return;
+        };
+
+        // Stages only run once a frame, but a query must not be written twice
+        let written = self.written.get();
+        if written & stage.bit() != 0 {
+            return;
+        }
+
+        encoder.write_timestamp(&queries.set, 2 * stage as u32 + end as u32);
+        if end {
+            self.written.set(written | stage.bit());
+        }
+    }
+
+    // Writes a timestamp in a submission of its own, for stages spread over several submissions
+    pub fn stamp_now(&self, device: &wgpu::Device, queue: &wgpu::Queue, stage: Stage, end: bool) {
+        if self.queries.is_none() || !self.recording {
+            return;
+        }
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Timestamp encoder"),
+        });
+
+        self.stamp(&mut encoder, stage, end);
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+
+    // Resolves the stages written in the frame into the readback buffer. Frames without
+    // timestamps are complete right away.
+    pub fn end_frame(&mut self, encoder: &mut wgpu::CommandEncoder) -> Option<FrameTimes> {
+        if !self.recording {
+            return None;
+        }
+
+        let Some(queries) = &self.queries else {
+            return Some(self.frame);
+        };
+
+        let written = self.written.get();
+        for stage in Stage::ALL.iter().filter(|s| written & s.bit() != 0) {
+            let first = 2 * *stage as u32;
+            encoder.resolve_query_set(
+                &queries.set,
+                first..first + 2,
+                &queries.resolve_buffer,
+                RESOLVE_STRIDE * *stage as u64,
+            );
+        }
+
+        encoder.copy_buffer_to_buffer(
+            &queries.resolve_buffer,
+            0,
+            &queries.readback_buffer,
+            0,
+            queries.resolve_buffer.size(),
+        );
+
+        self.pending = (written, self.frame);
+        None
+    }
+
+    // Starts mapping the readback buffer once the frame has been submitted
+    pub fn request_readback(&mut self) {
+        let Some(queries) = self.queries.as_ref().filter(|_| self.recording) else {
+            return;
+        };
+
+        let mapped = Arc::new(AtomicBool::new(false));
+        let callback_mapped = mapped.clone();
+        queries
+            .readback_buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                callback_mapped.store(result.is_ok(), Ordering::Release);
+            });
+
+        self.readback = Some(mapped);
+        self.recording = false;
+    }
+
+    // Returns the times of the measured frame once the readback has finished, without blocking
+    pub fn poll(&mut self, device: &wgpu::Device) -> Option<FrameTimes> {
+        let queries = self.queries.as_ref()?;
+        device.poll(wgpu::Maintain::Poll);
+        if !self.readback.as_ref()?.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let ticks: Vec<u64> = {
+            let bytes = queries.readback_buffer.slice(..).get_mapped_range();
+            bytemuck::pod_collect_to_vec(&bytes)
+        };
+
+        queries.readback_buffer.unmap();
+        self.readback = None;
+
+        let (written, frame) = self.pending;
+        Some(FrameTimes {
+            stages: durations(&ticks, written, self.period),
+            ..frame
+        })
+    }
+}
+
+// Milliseconds between the pair of timestamps of every written stage, laid out as resolved
+fn durations(ticks: &[u64], written: u32, period: f32) -> [Option<f32>; Stage::ALL.len()] {
+    let stride = (RESOLVE_STRIDE / 8) as usize;
+    Stage::ALL.map(|stage| {
+        let first = stage as usize * stride;
+        (written & stage.bit() != 0).then(|| {
+            let elapsed = ticks[first + 1].saturating_sub(ticks[first]);
+            elapsed as f32 * period * 1e-6
+        })
+    })
+}
+
+// Appends the times of a frame, dropping the oldest ones beyond the length of the history
+pub fn record(history: &mut VecDeque<FrameTimes>, times: FrameTimes) {
+    if history.len() == HISTORY {
+        history.pop_front();
+    }
+
+    history.push_back(times);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_durations() {
+        let stride = (RESOLVE_STRIDE / 8) as usize;
+        let mut ticks = vec![0; stride * Stage::ALL.len()];
+        ticks[Stage::Compute as usize * stride..][..2].copy_from_slice(&[1_000, 3_000_000]);
+        ticks[Stage::Gui as usize * stride..][..2].copy_from_slice(&[500, 100]);
+
+        // Only written stages count, at 2 ns per tick, and clocks going backwards give zero
+        let written = Stage::Compute.bit() | Stage::Gui.bit();
+        let stages = durations(&ticks, written, 2.0);
+        assert_eq!(stages[Stage::Upload as usize], None);
+        assert_eq!(stages[Stage::Gui as usize], Some(0.0));
+
+        let times = FrameTimes {
+            stages,
+            orbit: None,
+        };
+        assert!((times.gpu() - 5.998).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_record() {
+        let mut history = VecDeque::new();
+        for i in 0..HISTORY + 5 {
+            let orbit = Some(i as f32);
+            record(
+                &mut history,
+                FrameTimes {
+                    orbit,
+                    ..FrameTimes::default()
+                },
+            );
+        }
+
+        assert_eq!(history.len(), HISTORY);
+        assert_eq!(history.front().unwrap().orbit, Some(5.0));
+        assert_eq!(history.back().unwrap().orbit, Some((HISTORY + 4) as f32));
+    }
+}
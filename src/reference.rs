@@ -0,0 +1,174 @@
+use glam::{
+    f32::{vec2, Vec2},
+    f64::DVec2,
+    u32::UVec4,
+};
+
+use crate::{
+    exponential::{self, Projection},
+    storage,
+};
+
+/*
+    Several reference orbits can share a frame, which is split into a fixed grid of tiles that
+    each perturb against the reference closest to them. Deltas then stay small all over the frame
+    instead of only near a single reference. References lie on an even grid around the primary
+    one, which keeps its place while panning, and their orbits are stored one after another.
+*/
+
+// Tiles along each axis of the frame. Must be kept in sync with `REFERENCE_TILES` in compute.wgsl
+pub const TILES: u32 = 16;
+pub const TILE_COUNT: usize = (TILES * TILES) as usize;
+
+// Most references along each axis, and in total
+pub const MAX_GRID: u32 = 4;
+pub const MAX_REFERENCES: usize = (MAX_GRID * MAX_GRID) as usize;
+
+// Size of the view on the plane in units of the scale. Exponential maps cover the disk of the
+// outermost radius.
+pub fn extent(width: u32, height: u32, projection: Projection) -> Vec2 {
+    match projection {
+        Projection::Flat => vec2(width as f32 / height as f32, 1.0),
+        Projection::Exponential => Vec2::splat(2.0),
+    }
+}
+
+// Offsets of `size * size` references from the primary one, in units of the scale, at the middle
+// of every cell of an even grid over the extent of the view
+pub fn grid(size: u32, extent: Vec2) -> Vec<Vec2> {
+    (0..size)
+        .flat_map(|j| (0..size).map(move |i| vec2(i as f32, j as f32)))
+        .map(|cell| extent * ((cell + 0.5) / size as f32 - 0.5))
+        .collect()
+}
+
+// Offset from the center of the view at a position in pixels, in units of the scale, as in
+// `sample_at` in compute.wgsl
+fn offset(position: Vec2, width: u32, height: u32, projection: Projection) -> Vec2 {
+    match projection {
+        Projection::Flat => {
+            let uv = position / vec2(width as f32, height as f32);
+            extent(width, height, projection) * (uv - 0.5)
+        }
+        Projection::Exponential => exponential::offset(position, width, height),
+    }
+}
+
+// Tile of the map a position in pixels falls into
+pub fn tile(position: Vec2, width: u32, height: u32) -> usize {
+    let cell = position * TILES as f32 / vec2(width as f32, height as f32);
+    let cell = cell
+        .clamp(Vec2::ZERO, Vec2::splat((TILES - 1) as f32))
+        .as_uvec2();
+    (cell.y * TILES + cell.x) as usize
+}
+
+// Index of the reference closest to the middle of every tile, row by row from the bottom.
+// References are given as offsets from the center of the view in units of the scale.
+pub fn assign(width: u32, height: u32, projection: Projection, references: &[Vec2]) -> Vec<u32> {
+    let tile_size = vec2(width as f32, height as f32) / TILES as f32;
+    (0..TILES)
+        .flat_map(|y| (0..TILES).map(move |x| vec2(x as f32, y as f32)))
+        .map(|cell| {
+            let middle = offset((cell + 0.5) * tile_size, width, height, projection);
+            references
+                .iter()
+                .enumerate()
+                .min_by(|(_, a), (_, b)| {
+                    a.distance_squared(middle)
+                        .total_cmp(&b.distance_squared(middle))
+                })
+                .map_or(0, |(i, _)| i as u32)
+        })
+        .collect()
+}
+
+// Size of the uniform of native_f64.wgsl, a scale and an offset for every reference, each aligned
+// to 16 bytes
+pub const NATIVE_UNIFORM_SIZE: u64 = 16 * (1 + MAX_REFERENCES as u64);
+
+// Packs the scale and reference offsets for the native kernel, in the layout of its uniform
+pub fn native_uniform(scale: f64, offsets: &[DVec2]) -> Vec<f64> {
+    let mut packed = vec![0.0; NATIVE_UNIFORM_SIZE as usize / 8];
+    packed[0] = scale;
+    for (element, offset) in packed[2..].chunks_mut(2).zip(offsets) {
+        element.copy_from_slice(&offset.to_array());
+    }
+
+    packed
+}
+
+// Packs references and their map into the uniform read by the compute kernel
+pub fn uniform(references: &[storage::Reference], map: &[u32]) -> storage::References {
+    let mut packed = storage::References {
+        count: references.len() as u32,
+        references: [storage::Reference::default(); MAX_REFERENCES],
+        map: [UVec4::ZERO; TILE_COUNT / 4],
+    };
+
+    packed.references[..references.len()].copy_from_slice(references);
+    for (element, chunk) in packed.map.iter_mut().zip(map.chunks(4)) {
+        *element = UVec4::from_slice(chunk);
+    }
+
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::Storable;
+
+    #[test]
+    fn test_grid() {
+        let extent = extent(200, 100, Projection::Flat);
+        assert_eq!(grid(1, extent), [Vec2::ZERO]);
+        assert_eq!(
+            grid(2, extent),
+            [
+                vec2(-0.5, -0.25),
+                vec2(0.5, -0.25),
+                vec2(-0.5, 0.25),
+                vec2(0.5, 0.25)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_assign() {
+        let (width, height) = (200, 100);
+        let references = grid(2, extent(width, height, Projection::Flat));
+        let map = assign(width, height, Projection::Flat, &references);
+        assert_eq!(map.len(), TILE_COUNT);
+
+        // Every quadrant of the frame takes the reference in its middle
+        let at = |x: f32, y: f32| map[tile(vec2(x, y), width, height)];
+        assert_eq!(at(10.0, 10.0), 0);
+        assert_eq!(at(190.0, 10.0), 1);
+        assert_eq!(at(10.0, 90.0), 2);
+        assert_eq!(at(190.0, 90.0), 3);
+
+        // Positions on or past the edges fall into the outermost tiles
+        assert_eq!(tile(vec2(200.0, 100.0), width, height), TILE_COUNT - 1);
+        assert_eq!(tile(vec2(-1.0, 0.0), width, height), 0);
+
+        let packed = uniform(&[storage::Reference::default(); 4], &map);
+        assert_eq!(packed.count, 4);
+        assert_eq!(packed.map[0].to_array(), [0, 0, 0, 0]);
+        assert_eq!(packed.map[TILE_COUNT / 4 - 1].w, 3);
+
+        // Fails when the layout breaks the rules of uniforms
+        assert!(!storage::Uniform(&packed).to_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_native_uniform() {
+        let offsets = [DVec2::new(1e-200, -2e-200), DVec2::new(3e-200, 4e-200)];
+        let packed = native_uniform(1e-190, &offsets);
+        assert_eq!(packed.len() * 8, NATIVE_UNIFORM_SIZE as usize);
+
+        // Scales past the exponent range of f32 survive, and every offset starts on 16 bytes
+        assert_eq!(packed[..6], [1e-190, 0.0, 1e-200, -2e-200, 3e-200, 4e-200]);
+        assert!(packed[6..].iter().all(|&x| x == 0.0));
+    }
+}
@@ -0,0 +1,175 @@
+use glam::f32::{vec2, Vec2};
+
+use crate::storage;
+
+/*
+    Adaptive anti-aliasing. After a frame is rendered, pixels whose neighbourhood varies strongly
+    in iteration count or distance estimate are picked out and rendered again with a grid of extra
+    sub-pixel samples, which the colouring pass blends in with the regular samples. Every refined
+    pixel owns a slot of `SAMPLES` channels in a compact buffer, and a per-pixel map holds its slot
+    plus one, or zero for pixels that were left alone. The GPU does the same in compute.wgsl.
+*/
+
+// Must be kept in sync with `REFINE_GRID_SIZE` in compute.wgsl and colouring.wgsl
+pub const GRID_SIZE: u32 = 3;
+pub const SAMPLES: usize = (GRID_SIZE * GRID_SIZE) as usize;
+
+// At most one pixel in this many is refined, which bounds the memory of the refined samples
+const CAPACITY_FRACTION: usize = 8;
+
+// Standard deviation of the iteration count, and of the distance estimate in octaves, above
+// which a pixel is refined
+pub const DEFAULT_THRESHOLD: f32 = 0.5;
+
+// Slots available for a frame, or a single unused one when refinement is off so buffers never
+// end up empty
+pub fn capacity(width: u32, height: u32, refine: bool) -> usize {
+    if refine {
+        (width as usize * height as usize).div_ceil(CAPACITY_FRACTION)
+    } else {
+        1
+    }
+}
+
+// Offsets of the extra samples from the corner of a pixel, at the centers of a regular grid
+pub fn offset(sample: usize) -> Vec2 {
+    let cell = vec2(
+        (sample as u32 % GRID_SIZE) as f32,
+        (sample as u32 / GRID_SIZE) as f32,
+    );
+
+    (cell + 0.5) / GRID_SIZE as f32
+}
+
+// Whether the 3x3 neighbourhood of a pixel is mixed between escaped and interior pixels, or
+// varies by more than the threshold
+fn needs_refinement(
+    channels: &[storage::Channels],
+    width: u32,
+    height: u32,
+    (x, y): (u32, u32),
+    iterations: usize,
+    threshold: f32,
+) -> bool {
+    let value = |channels: &storage::Channels| {
+        vec2(
+            channels.iterations * iterations as f32,
+            channels.distance.max(1e-30).log2(),
+        )
+    };
+
+    // Values are taken relative to the center to keep the variance precise
+    let center = value(&channels[(y * width + x) as usize]);
+    let (mut count, mut escaped) = (0, 0);
+    let (mut sum, mut sum_sqr) = (Vec2::ZERO, Vec2::ZERO);
+    for ny in y.saturating_sub(1)..(y + 2).min(height) {
+        for nx in x.saturating_sub(1)..(x + 2).min(width) {
+            let neighbour = &channels[(ny * width + nx) as usize];
+            count += 1;
+            if neighbour.flags & storage::Channels::ESCAPED != 0 {
+                escaped += 1;
+                let v = value(neighbour) - center;
+                sum += v;
+                sum_sqr += v * v;
+            }
+        }
+    }
+
+    if escaped == 0 {
+        return false;
+    }
+
+    if escaped < count {
+        return true;
+    }
+
+    let mean = sum / count as f32;
+    let variance = (sum_sqr / count as f32 - mean * mean).max(Vec2::ZERO);
+    variance.cmpgt(Vec2::splat(threshold * threshold)).any()
+}
+
+// Pixels of the first plane of a frame that need refinement in row order, up to the capacity
+pub fn detect(
+    channels: &[storage::Channels],
+    width: u32,
+    height: u32,
+    iterations: usize,
+    threshold: f32,
+) -> Vec<u32> {
+    (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .filter(|&pixel| needs_refinement(channels, width, height, pixel, iterations, threshold))
+        .map(|(x, y)| y * width + x)
+        .take(capacity(width, height, true))
+        .collect()
+}
+
+// Per-pixel map from the detected pixels, holding the slot of every refined pixel plus one
+pub fn slot_map(pixels: &[u32], width: u32, height: u32) -> Vec<u32> {
+    let mut map = vec![0; (width * height) as usize];
+    for (slot, &pixel) in pixels.iter().enumerate() {
+        map[pixel as usize] = slot as u32 + 1;
+    }
+
+    map
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect() {
+        // A smooth gradient of escaped pixels with an interior block in the bottom right corner
+        let (width, height) = (8, 8);
+        let channels: Vec<storage::Channels> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| storage::Channels {
+                iterations: (10.0 + 0.2 * x as f32) / 100.0,
+                distance: 1.0,
+                flags: if x >= 6 && y >= 6 {
+                    0
+                } else {
+                    storage::Channels::ESCAPED
+                },
+                ..Default::default()
+            })
+            .collect();
+
+        let pixels = detect(&channels, width, height, 100, DEFAULT_THRESHOLD);
+
+        // Only the pixels whose neighbourhood straddles the interior block are refined
+        let expected: Vec<u32> = (5..8)
+            .flat_map(|y| (5..8).map(move |x| y * width + x))
+            .filter(|&pixel| pixel != 7 * width + 7)
+            .collect();
+
+        assert_eq!(pixels, expected);
+
+        let map = slot_map(&pixels, width, height);
+        assert_eq!(map[(5 * width + 5) as usize], 1);
+        assert_eq!(map[0], 0);
+
+        // A steep gradient is refined everywhere, up to the capacity
+        let steep: Vec<storage::Channels> = channels
+            .iter()
+            .map(|c| storage::Channels {
+                iterations: c.iterations * 10.0,
+                flags: storage::Channels::ESCAPED,
+                ..*c
+            })
+            .collect();
+
+        assert_eq!(
+            detect(&steep, width, height, 100, DEFAULT_THRESHOLD).len(),
+            8
+        );
+    }
+
+    #[test]
+    fn test_offsets() {
+        let offsets: Vec<Vec2> = (0..SAMPLES).map(offset).collect();
+        assert_eq!(offsets[0], Vec2::splat(1.0 / 6.0));
+        assert_eq!(offsets[SAMPLES - 1], Vec2::splat(5.0 / 6.0));
+    }
+}
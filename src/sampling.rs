@@ -0,0 +1,66 @@
+use glam::f32::{vec2, Vec2};
+use nanorand::{Rng, WyRand};
+
+/*
+    Sub-pixel sample positions for supersampling. Every pixel is split into an n x n grid with one
+    randomly placed sample per cell, and the colouring pass resolves the samples of a pixel with a
+    reconstruction filter.
+*/
+
+// Samples per axis that can be selected, the square of which is the samples per pixel
+pub const GRID_SIZES: [u32; 4] = [1, 2, 3, 4];
+
+// Fixed so the sample positions, and thus the image, are the same in every frame
+const SEED: u64 = 0x5eed;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reconstruction {
+    Box,
+    Gaussian,
+}
+
+impl Reconstruction {
+    pub const ALL: [Reconstruction; 2] = [Reconstruction::Box, Reconstruction::Gaussian];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Reconstruction::Box => "Box",
+            Reconstruction::Gaussian => "Gaussian",
+        }
+    }
+}
+
+// Offsets from the corner of a pixel, in pixels. A single sample sits on the corner itself, so
+// turning supersampling off renders exactly what it did before.
+pub fn jittered(grid_size: u32) -> Vec<Vec2> {
+    if grid_size <= 1 {
+        return vec![Vec2::ZERO];
+    }
+
+    let mut rng = WyRand::new_seed(SEED);
+    let cell = 1.0 / grid_size as f32;
+    (0..grid_size)
+        .flat_map(|y| (0..grid_size).map(move |x| vec2(x as f32, y as f32)))
+        .map(|corner| (corner + vec2(rng.generate::<f32>(), rng.generate::<f32>())) * cell)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jittered() {
+        assert_eq!(jittered(1), [Vec2::ZERO]);
+
+        // Every cell of the grid holds exactly one sample
+        let samples = jittered(3);
+        assert_eq!(samples.len(), 9);
+        for (i, sample) in samples.iter().enumerate() {
+            let cell = (*sample * 3.0).floor().min(Vec2::splat(2.0));
+            assert_eq!(cell, vec2((i % 3) as f32, (i / 3) as f32));
+        }
+
+        assert_eq!(jittered(3), samples);
+    }
+}
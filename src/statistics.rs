@@ -0,0 +1,133 @@
+use crate::storage;
+
+/*
+    Counts and ranges over every rendered sample of a frame, for display and automation. The GPU
+    reduces the channel buffer into the same words in histogram.wgsl, these are the CPU versions for
+    frames that stay in memory. Samples that have not been rendered yet are left out.
+*/
+
+// Words of the statistics buffer, must be kept in sync with `Statistics` in histogram.wgsl
+pub const WORDS: usize = 9;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Statistics {
+    // Length of the orbits the frame was rendered with
+    pub iterations: u32,
+    pub escaped: u32,
+    // Samples whose orbit is attracted to a cycle, and the other ones that reached the limit
+    pub interior: u32,
+    pub max_iteration: u32,
+    // Samples that lost precision against their reference, counted among the others as well
+    pub glitched: u32,
+    // First and last iteration at which a sample escaped, if any did
+    pub escape_range: Option<(u32, u32)>,
+    // Iterations saved per sample by filling in samples rather than evaluating them
+    pub skipped: f32,
+}
+
+impl Statistics {
+    // Every sample that has been rendered
+    pub fn samples(&self) -> u32 {
+        self.escaped + self.interior + self.max_iteration
+    }
+
+    // Reads the words reduced on the GPU
+    pub fn from_words(words: &[u32]) -> Self {
+        let skipped = (words[8] as u64) << 32 | words[7] as u64;
+        let mut statistics = Self {
+            iterations: words[0],
+            escaped: words[1],
+            interior: words[2],
+            max_iteration: words[3],
+            glitched: words[4],
+            escape_range: (words[5] <= words[6]).then_some((words[5], words[6])),
+            skipped: 0.0,
+        };
+
+        statistics.skipped = skipped as f32 / statistics.samples().max(1) as f32;
+        statistics
+    }
+}
+
+// Contents of the statistics buffer before a reduction, which starts from the length of the orbits
+pub fn initial_words(iterations: usize) -> [u32; WORDS] {
+    let mut words = [0; WORDS];
+    words[0] = iterations as u32;
+    words[5] = u32::MAX;
+    words
+}
+
+pub fn accumulate(channels: &[storage::Channels], iterations: usize) -> Statistics {
+    let mut words = initial_words(iterations);
+    let mut skipped = 0;
+    for channels in channels {
+        if channels.flags & storage::Channels::PENDING != 0 {
+            continue;
+        }
+
+        let escape = (channels.iterations * iterations as f32) as u32;
+        if channels.flags & storage::Channels::ESCAPED != 0 {
+            words[1] += 1;
+            words[5] = words[5].min(escape);
+            words[6] = words[6].max(escape);
+        } else if channels.flags & storage::Channels::INTERIOR != 0 {
+            words[2] += 1;
+        } else {
+            words[3] += 1;
+        }
+
+        if channels.flags & storage::Channels::GLITCHED != 0 {
+            words[4] += 1;
+        }
+
+        if channels.flags & storage::Channels::FILLED != 0 {
+            skipped += escape as u64;
+        }
+    }
+
+    words[7] = skipped as u32;
+    words[8] = (skipped >> 32) as u32;
+    Statistics::from_words(&words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_accumulate() {
+        let sample = |iterations, flags| storage::Channels {
+            iterations,
+            flags,
+            ..Default::default()
+        };
+
+        let channels = [
+            sample(0.25, storage::Channels::ESCAPED),
+            sample(0.5, storage::Channels::ESCAPED | storage::Channels::FILLED),
+            sample(1.0, storage::Channels::INTERIOR),
+            sample(1.0, storage::Channels::GLITCHED),
+            sample(0.0, storage::Channels::PENDING),
+        ];
+
+        let statistics = accumulate(&channels, 100);
+        assert_eq!(statistics.iterations, 100);
+        assert_eq!(statistics.samples(), 4);
+        assert_eq!(
+            (
+                statistics.escaped,
+                statistics.interior,
+                statistics.max_iteration
+            ),
+            (2, 1, 1)
+        );
+        assert_eq!(statistics.glitched, 1);
+        assert_eq!(statistics.escape_range, Some((25, 50)));
+        assert_eq!(statistics.skipped, 12.5);
+
+        // Nothing escaping leaves the range empty
+        let statistics = accumulate(&channels[2..], 100);
+        assert_eq!(statistics.escape_range, None);
+        assert_eq!(statistics.skipped, 0.0);
+    }
+}
@@ -1,10 +1,11 @@
 use encase::{
-    private::WriteInto, ArrayLength, ShaderSize, ShaderType, StorageBuffer, UniformBuffer,
+    private::{CreateFrom, WriteInto},
+    ShaderSize, ShaderType, StorageBuffer, UniformBuffer,
 };
 use glam::f32;
 
 pub trait Storable {
-    fn into_bytes(&self) -> Vec<u8>;
+    fn to_bytes(&self) -> Vec<u8>;
 }
 
 pub struct Uniform<'a, T>(pub &'a T)
@@ -15,7 +16,7 @@ impl<T> Storable for Uniform<'_, T>
 where
     T: ShaderType + ShaderSize + WriteInto,
 {
-    fn into_bytes(&self) -> Vec<u8> {
+    fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = UniformBuffer::new(Vec::new());
         buffer.write(self.0).expect("Unable to write uniform");
         buffer.into_inner()
@@ -26,51 +27,176 @@ pub struct Buffer<'a, T>(pub &'a [T])
 where
     T: ShaderSize;
 
-#[derive(ShaderType)]
-struct SizedBuffer<'a, T: ShaderSize + 'a> {
-    length: ArrayLength,
-
-    #[size(runtime)]
-    buffer: &'a [T],
-}
-
-impl<'a, T> SizedBuffer<'a, T>
+impl<T> Storable for Buffer<'_, T>
 where
-    T: ShaderSize + 'a,
+    T: ShaderSize + WriteInto,
 {
-    fn new(buffer: &'a [T]) -> Self {
-        Self {
-            length: ArrayLength,
-            buffer,
-        }
+    fn to_bytes(&self) -> Vec<u8> {
+        let data = SizedBuffer::new(self.0);
+        let mut buffer = StorageBuffer::new(Vec::new());
+        buffer.write(&data).expect("Unable to write buffer");
+
+        buffer.into_inner()
     }
 }
 
-impl<T> Storable for Buffer<'_, T>
+// A runtime-sized array without a length, as bound by the channel buffers
+pub struct Array<'a, T>(pub &'a [T])
+where
+    T: ShaderSize;
+
+impl<T> Storable for Array<'_, T>
 where
     T: ShaderSize + WriteInto,
 {
-    fn into_bytes(&self) -> Vec<u8> {
-        let data = SizedBuffer::new(self.0);
+    fn to_bytes(&self) -> Vec<u8> {
         let mut buffer = StorageBuffer::new(Vec::new());
-        buffer.write(&data).expect("Unable to write buffer");
-
+        buffer.write(&self.0).expect("Unable to write array");
         buffer.into_inner()
     }
 }
 
+pub fn array_from_bytes<T>(bytes: &[u8]) -> Vec<T>
+where
+    T: ShaderSize + CreateFrom,
+{
+    StorageBuffer::new(bytes)
+        .create()
+        .expect("Unable to read array")
+}
+
 /*
     Types
 */
 
-#[derive(ShaderType)]
-pub struct Globals {
-    pub time: f32,
-    pub scale: f32,
-    pub radius: f32,
-    pub center: f32::Vec2,
-    pub orbit_offset: f32::Vec2,
-    pub coefficients: [f32::Vec4; 4],
+pub use layout::*;
+
+// The ShaderType derive emits layout assertions that newer compilers report as unused, the derived
+// types are kept in a module of their own so the lint is only silenced for them
+#[allow(dead_code)]
+mod layout {
+    use encase::{ArrayLength, ShaderSize, ShaderType};
+    use glam::f32;
+
+    use crate::reference;
+
+    #[derive(ShaderType)]
+    pub(super) struct SizedBuffer<'a, T: ShaderSize + 'a> {
+        length: ArrayLength,
+
+        #[size(runtime)]
+        buffer: &'a [T],
+    }
+
+    impl<'a, T> SizedBuffer<'a, T>
+    where
+        T: ShaderSize + 'a,
+    {
+        pub(super) fn new(buffer: &'a [T]) -> Self {
+            Self {
+                length: ArrayLength,
+                buffer,
+            }
+        }
+    }
+
+    #[derive(ShaderType, Clone, Copy, PartialEq)]
+    pub struct Globals {
+        pub time: f32,
+        pub scale: f32,
+        pub radius: f32,
+        pub stripe_density: f32,
+        pub refine_threshold: f32,
+        // Layout of the pixels on the plane, see exponential.rs
+        pub projection: u32,
+        // Arithmetic of the perturbation step, and the low parts of the values it needs in float-float
+        pub delta_precision: u32,
+        pub scale_lo: f32,
+        pub center: f32::Vec2,
+        pub coefficients: [f32::Vec4; 4],
+    }
+
+    // Offset of the start of a reference orbit from the center of the view, in float-float
+    #[derive(ShaderType, Clone, Copy, Default, Debug, PartialEq)]
+    pub struct Reference {
+        pub offset: f32::Vec2,
+        pub offset_lo: f32::Vec2,
+    }
+
+    // Every reference of a view and the one each tile perturbs against, four tiles to an element, see
+    // reference.rs
+    #[derive(ShaderType)]
+    pub struct References {
+        pub references: [Reference; reference::MAX_REFERENCES],
+        pub map: [glam::u32::UVec4; reference::TILE_COUNT / 4],
+        pub count: u32,
+    }
+
+    #[derive(ShaderType, Clone, Copy, Default, PartialEq)]
+    pub struct Trap {
+        pub kind: u32,
+        pub radius: f32,
+        pub position: f32::Vec2,
+        pub direction: f32::Vec2,
+    }
+
+    // Settings of the colouring pass, which can change without recomputing any channels
+    #[derive(ShaderType, Clone, Copy)]
+    pub struct ColouringGlobals {
+        pub colouring: u32,
+        pub equalize: u32,
+        pub reconstruction: u32,
+        pub show_subdivision: u32,
+        pub show_refinement: u32,
+        pub pixel_size: f32,
+        pub palette_repeat: f32,
+        pub palette_offset: f32,
+        pub interior: f32::Vec3,
+    }
+
+    // Raw per-pixel output of the compute pass
+    #[derive(ShaderType, Clone, Copy, Default, Debug, PartialEq)]
+    pub struct Channels {
+        pub iterations: f32,
+        pub trap_distance: f32,
+        pub trap_iteration: f32,
+        pub triangle_inequality: f32,
+        pub stripe: f32,
+        pub curvature: f32,
+        pub final_z: f32::Vec2,
+        pub distance: f32,
+        pub flags: u32,
+    }
+
+    impl Channels {
+        // Must be kept in sync with the flags in compute.wgsl and colouring.wgsl
+        pub const ESCAPED: u32 = 1;
+        pub const FILLED: u32 = 2;
+        // Not rendered yet, only ever seen in the buffers of the GPU engine
+        pub const PENDING: u32 = 4;
+        // Lost precision against the reference orbit, see `GLITCH_TOLERANCE` in compute.wgsl
+        pub const GLITCHED: u32 = 8;
+        // Never escaped, and attracted to a cycle rather than just out of iterations
+        pub const INTERIOR: u32 = 16;
+    }
+
+    // Maps a position on the screen, in uv coordinates, onto the placeholder image
+    #[derive(ShaderType, Clone, Copy, PartialEq)]
+    pub struct Reprojection {
+        pub scale: f32::Vec2,
+        pub offset: f32::Vec2,
+    }
+
+    // Tile covered by a single compute dispatch, the progressive pass it renders and the sample plane
+    // it renders into. Refinement dispatches cover a range of refined samples starting at `origin.x`.
+    #[derive(ShaderType)]
+    pub struct Tile {
+        pub origin: glam::u32::UVec2,
+        pub dimensions: glam::u32::UVec2,
+        pub pass_index: u32,
+        pub pass_count: u32,
+        pub plane: u32,
+    }
 }
 
 #[repr(C)]
@@ -0,0 +1,223 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Must be kept in sync with `SUBDIVISION_TILE_SIZE` in compute.wgsl
+pub const TILE_SIZE: u32 = 16;
+
+// Rectangles this small are evaluated pixel by pixel instead of being split further
+const MIN_SIZE: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+pub struct Subdivision<T> {
+    pub values: Vec<T>,
+    // Rectangles whose interior was filled from their border without being evaluated
+    pub filled: Vec<Rect>,
+}
+
+impl Rect {
+    fn border(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        let (x0, y0) = (self.x, self.y);
+        let (x1, y1) = (self.x + self.width - 1, self.y + self.height - 1);
+        let horizontal = (x0..=x1).flat_map(move |x| [(x, y0), (x, y1)]);
+        let vertical = (y0 + 1..y1).flat_map(move |y| [(x0, y), (x1, y)]);
+        horizontal.chain(vertical)
+    }
+
+    fn interior(&self) -> impl Iterator<Item = (u32, u32)> + '_ {
+        (self.y + 1..self.y + self.height - 1)
+            .flat_map(move |y| (self.x + 1..self.x + self.width - 1).map(move |x| (x, y)))
+    }
+
+    // Splits into quadrants that share their inner edges
+    fn split(&self) -> [Rect; 4] {
+        let (w0, h0) = (self.width / 2 + 1, self.height / 2 + 1);
+        let (w1, h1) = (self.width - w0 + 1, self.height - h0 + 1);
+        let (xm, ym) = (self.x + w0 - 1, self.y + h0 - 1);
+        [
+            Rect::new(self.x, self.y, w0, h0),
+            Rect::new(xm, self.y, w1, h0),
+            Rect::new(self.x, ym, w0, h1),
+            Rect::new(xm, ym, w1, h1),
+        ]
+    }
+
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+struct Tile<T> {
+    rect: Rect,
+    values: Vec<Option<T>>,
+    filled: Vec<Rect>,
+}
+
+impl<T: Clone> Tile<T> {
+    fn get_or_evaluate<F>(&mut self, x: u32, y: u32, evaluate: &F) -> T
+    where
+        F: Fn(u32, u32) -> T,
+    {
+        let index = ((y - self.rect.y) * self.rect.width + (x - self.rect.x)) as usize;
+        self.values[index]
+            .get_or_insert_with(|| evaluate(x, y))
+            .clone()
+    }
+
+    fn set(&mut self, x: u32, y: u32, value: T) {
+        let index = ((y - self.rect.y) * self.rect.width + (x - self.rect.x)) as usize;
+        self.values[index] = Some(value);
+    }
+
+    fn subdivide<F, K, U>(&mut self, rect: Rect, evaluate: &F, key: &U)
+    where
+        F: Fn(u32, u32) -> T,
+        K: PartialEq,
+        U: Fn(&T) -> K,
+    {
+        let border: Vec<(u32, u32)> = rect.border().collect();
+        let values: Vec<T> = border
+            .iter()
+            .map(|(x, y)| self.get_or_evaluate(*x, *y, evaluate))
+            .collect();
+
+        if rect.width <= 2 || rect.height <= 2 {
+            return;
+        }
+
+        let first = key(&values[0]);
+        if values.iter().all(|v| key(v) == first) {
+            for (x, y) in rect.interior() {
+                self.set(x, y, values[0].clone());
+            }
+
+            self.filled.push(rect);
+        } else if rect.width <= MIN_SIZE || rect.height <= MIN_SIZE {
+            for (x, y) in rect.interior() {
+                self.get_or_evaluate(x, y, evaluate);
+            }
+        } else {
+            for quadrant in rect.split() {
+                self.subdivide(quadrant, evaluate, key);
+            }
+        }
+    }
+}
+
+// Renders an image with Mariani-Silver subdivision: every tile's border is evaluated first and
+// the tile is filled without further evaluation when all border pixels share the same key.
+// Otherwise, the tile is split into quadrants which are processed the same way.
+pub fn render<T, K, F, U>(width: u32, height: u32, evaluate: F, key: U) -> Subdivision<T>
+where
+    T: Clone + Send,
+    K: PartialEq,
+    F: Fn(u32, u32) -> T + Sync,
+    U: Fn(&T) -> K + Sync,
+{
+    let tiles_x = width.div_ceil(TILE_SIZE);
+    let tiles_y = height.div_ceil(TILE_SIZE);
+    let tile_count = (tiles_x * tiles_y) as usize;
+
+    let next_tile = AtomicUsize::new(0);
+    let tiles: Vec<Tile<T>> = std::thread::scope(|scope| {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let handles: Vec<_> = (0..workers)
+            .map(|_| {
+                scope.spawn(|| {
+                    let mut tiles = Vec::new();
+                    loop {
+                        let index = next_tile.fetch_add(1, Ordering::Relaxed);
+                        if index >= tile_count {
+                            break tiles;
+                        }
+
+                        let x = (index as u32 % tiles_x) * TILE_SIZE;
+                        let y = (index as u32 / tiles_x) * TILE_SIZE;
+                        let rect =
+                            Rect::new(x, y, TILE_SIZE.min(width - x), TILE_SIZE.min(height - y));
+
+                        let mut tile = Tile {
+                            rect,
+                            values: vec![None; (rect.width * rect.height) as usize],
+                            filled: Vec::new(),
+                        };
+
+                        tile.subdivide(rect, &evaluate, &key);
+                        tiles.push(tile);
+                    }
+                })
+            })
+            .collect();
+
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("Subdivision worker panicked"))
+            .collect()
+    });
+
+    let mut values: Vec<Option<T>> = vec![None; (width * height) as usize];
+    let mut filled = Vec::new();
+    for tile in tiles {
+        let rect = tile.rect;
+        for (i, value) in tile.values.into_iter().enumerate() {
+            let x = rect.x + i as u32 % rect.width;
+            let y = rect.y + i as u32 / rect.width;
+            values[(y * width + x) as usize] = value;
+        }
+
+        filled.extend(tile.filled);
+    }
+
+    Subdivision {
+        values: values
+            .into_iter()
+            .map(|v| v.expect("Pixel was neither evaluated nor filled"))
+            .collect(),
+        filled,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_covers_rect() {
+        let rect = Rect::new(3, 5, 16, 9);
+        let mut covered = std::collections::HashSet::new();
+        for quadrant in rect.split() {
+            covered.extend(quadrant.border());
+            covered.extend(quadrant.interior());
+        }
+
+        let expected: std::collections::HashSet<_> = rect.border().chain(rect.interior()).collect();
+        assert_eq!(covered, expected);
+    }
+
+    #[test]
+    fn test_render_matches_direct_evaluation() {
+        let (width, height) = (70, 45);
+        let evaluate = |x: u32, y: u32| {
+            let (dx, dy) = (x as f32 - 30.0, y as f32 - 20.0);
+            (dx * dx + dy * dy).sqrt() as u32 / 12
+        };
+
+        let subdivision = render(width, height, evaluate, |v| *v);
+        assert!(!subdivision.filled.is_empty());
+        for y in 0..height {
+            for x in 0..width {
+                assert_eq!(subdivision.values[(y * width + x) as usize], evaluate(x, y));
+            }
+        }
+    }
+}
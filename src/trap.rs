@@ -0,0 +1,65 @@
+use crate::storage;
+
+pub const MAX_TRAPS: usize = 16;
+
+// Image traps are not supported yet, they need an image loader and a texture bound to the compute
+// pass
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TrapKind {
+    Point,
+    Line,
+    Cross,
+    Circle,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Trap {
+    pub kind: TrapKind,
+    pub position: glam::f32::Vec2,
+    pub angle: f32,
+    pub radius: f32,
+}
+
+impl TrapKind {
+    pub const ALL: [TrapKind; 4] = [
+        TrapKind::Point,
+        TrapKind::Line,
+        TrapKind::Cross,
+        TrapKind::Circle,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TrapKind::Point => "Point",
+            TrapKind::Line => "Line",
+            TrapKind::Cross => "Cross",
+            TrapKind::Circle => "Circle",
+        }
+    }
+}
+
+impl Trap {
+    pub fn new(kind: TrapKind) -> Self {
+        Self {
+            kind,
+            position: glam::f32::Vec2::ZERO,
+            angle: 0.0,
+            radius: 0.5,
+        }
+    }
+
+    pub fn direction(&self) -> glam::f32::Vec2 {
+        glam::f32::Vec2::from_angle(self.angle)
+    }
+}
+
+impl From<&Trap> for storage::Trap {
+    fn from(trap: &Trap) -> Self {
+        Self {
+            kind: trap.kind as u32,
+            radius: trap.radius,
+            position: trap.position,
+            direction: trap.direction(),
+        }
+    }
+}